@@ -2,8 +2,8 @@ use proc_macro2::TokenStream;
 use quote::{format_ident, quote, quote_spanned, ToTokens};
 use ring::digest::{Context, SHA256};
 use syn::{
-    parse2, parse_quote, parse_str, spanned::Spanned, Data, Fields, ItemImpl, Path, Type,
-    WherePredicate,
+    parse2, parse_quote, parse_str, spanned::Spanned, Attribute, Data, Expr, ExprParen, Fields,
+    ItemImpl, Path, Type, WherePredicate,
 };
 use synstructure::{AddBounds, BindStyle, Structure};
 
@@ -71,8 +71,22 @@ pub fn derive(mut s: Structure) -> TokenStream {
         s.bind_with(|_| BindStyle::Move);
         s.add_bounds(AddBounds::Generics);
         let mut predicates: Vec<WherePredicate> = vec![];
+        let mut lost = TokenStream::new();
         let arms = s.each_variant(|variant| {
             let ident = variant.ast().ident;
+            if marks_lost(variant.ast().attrs) {
+                let id = &s.ast().ident;
+                let constructor = if is_struct { quote!(#id) } else { quote!(#id::#ident) };
+                lost = match variant.ast().fields {
+                    _ if !lost.is_empty() => quote_spanned!(ident.span() => compile_error!("only one variant can represent loss of the connection");),
+                    Fields::Unnamed(fields) if fields.unnamed.len() == 1 => quote! {
+                        fn lost(error: ::vessels::kind::TransportError) -> Option<Self> {
+                            Some(#constructor(error))
+                        }
+                    },
+                    _ => quote_spanned!(ident.span() => compile_error!("a variant marked `#[kind(lost)]` must wrap only a `TransportError`");),
+                };
+            }
             use Fields::{Named, Unit, Unnamed};
             let mut bindings = TokenStream::new();
             let fields = match variant.ast().fields {
@@ -90,15 +104,13 @@ pub fn derive(mut s: Structure) -> TokenStream {
                                 } else {
                                     let binding_ty = &binding.ast().ty;
                                     predicates.push(syn::parse_quote!(#binding_ty: ::vessels::kind::AsKind<#ty>));
-                                    quote! {
-                                        channel.fork(<#binding_ty as ::vessels::kind::AsKind<#ty>>::into_kind(#pat))
-                                    }
+                                    fork(binding.ast().attrs.as_slice(), quote!(<#binding_ty as ::vessels::kind::AsKind<#ty>>::into_kind(#pat)))
                                 }
                             } else {
                                 quote_spanned!(ty.span() => compile_error!("not a valid type"))
                             }
                         } else {
-                            quote!(channel.fork(#pat))
+                            fork(binding.ast().attrs.as_slice(), quote!(#pat))
                         };
                         items.extend(quote!(#ident: ::vessels::channel::ForkHandle,));
                         cons_extension.extend(quote!(#ident,));
@@ -115,15 +127,17 @@ pub fn derive(mut s: Structure) -> TokenStream {
                                 } else {
                                     let binding_ty = &field.ty;
                                     predicates.push(syn::parse_quote!(#binding_ty: ::vessels::kind::AsKind<#ty>));
+                                    let get_fork = get_fork(field.attrs.as_slice(), quote!(<#binding_ty as ::vessels::kind::AsKind<#ty>>::Kind), quote!(#pat));
                                     quote! {
-                                        <#binding_ty as ::vessels::kind::AsKind<#ty>>::from_kind(channel.get_fork::<<#binding_ty as ::vessels::kind::AsKind<#ty>>::Kind>(#pat).await.unwrap())
+                                        <#binding_ty as ::vessels::kind::AsKind<#ty>>::from_kind(#get_fork.await.unwrap())
                                     }
                                 }
                             } else {
                                 quote_spanned!(ty.span() => compile_error!("not a valid type"))
                             }
                         } else {
-                            quote!(channel.get_fork(#pat).await.unwrap())
+                            let get_fork = get_fork(field.attrs.as_slice(), quote!(_), quote!(#pat));
+                            quote!(#get_fork.await.unwrap())
                         }
                     });
                     cons_arms
@@ -148,15 +162,17 @@ pub fn derive(mut s: Structure) -> TokenStream {
                                 } else {
                                     let binding_ty = &binding.ast().ty;
                                     predicates.push(syn::parse_quote!(#binding_ty: ::vessels::kind::AsKind<#ty>));
+                                    let get_fork = get_fork(binding.ast().attrs.as_slice(), quote!(<#binding_ty as ::vessels::kind::AsKind<#ty>>::Kind), quote!(#pat));
                                     quote! {
-                                        <#binding_ty as ::vessels::kind::AsKind<#ty>>::from_kind(channel.get_fork::<<#binding_ty as ::vessels::kind::AsKind<#ty>>::Kind>(#pat).await.unwrap())
+                                        <#binding_ty as ::vessels::kind::AsKind<#ty>>::from_kind(#get_fork.await.unwrap())
                                     }
                                 }
                             } else {
                                 quote_spanned!(ty.span() => compile_error!("not a valid type"))
                             }
                         } else {
-                            quote!(channel.get_fork(#pat).await.unwrap())
+                            let get_fork = get_fork(binding.ast().attrs.as_slice(), quote!(_), quote!(#pat));
+                            quote!(#get_fork.await.unwrap())
                         };
                         cons_extension.extend(quote!(#pat,));
                         cons_c_extension.extend(quote!(#stream,));
@@ -205,7 +221,8 @@ pub fn derive(mut s: Structure) -> TokenStream {
                 } else {
                     quote!(#pat)
                 };
-                bindings.extend(quote!(channel.fork(#stream).await.unwrap(),))
+                let fork = fork(binding.ast().attrs.as_slice(), stream);
+                bindings.extend(quote!(#fork.await.unwrap(),))
             }
             quote! {
                 channel.send({
@@ -255,6 +272,8 @@ pub fn derive(mut s: Structure) -> TokenStream {
                         })
                     })
                 }
+
+                #lost
             }
         }));
         stream
@@ -270,6 +289,47 @@ pub fn derive(mut s: Structure) -> TokenStream {
     .into()
 }
 
+/// Returns the priority given to a field by a `priority` attribute, if any.
+fn priority(attrs: &[Attribute]) -> Option<TokenStream> {
+    let priority_attr = parse_str::<Path>("priority").unwrap();
+    let attr = attrs.iter().find(|attr| attr.path == priority_attr)?;
+    if let Ok(Expr::Path(path)) = parse2::<ExprParen>(attr.tokens.clone()).map(|expr| *expr.expr) {
+        if let Some(ident) = path.path.get_ident() {
+            return Some(quote!(::vessels::channel::Priority::#ident));
+        }
+    }
+    Some(
+        quote_spanned!(attr.span() => compile_error!("expected a priority such as `#[priority(High)]`")),
+    )
+}
+
+/// Returns whether a variant is marked with `#[kind(lost)]` as representing loss of the
+/// connection.
+fn marks_lost(attrs: &[Attribute]) -> bool {
+    let kind_attr = parse_str::<Path>("kind").unwrap();
+    attrs.iter().any(|attr| {
+        attr.path == kind_attr
+            && match parse2::<ExprParen>(attr.tokens.clone()).map(|expr| *expr.expr) {
+                Ok(Expr::Path(path)) => path.path.is_ident("lost"),
+                _ => false,
+            }
+    })
+}
+
+fn fork(attrs: &[Attribute], item: TokenStream) -> TokenStream {
+    match priority(attrs) {
+        Some(priority) => quote!(channel.fork_with_priority(#item, #priority)),
+        None => quote!(channel.fork(#item)),
+    }
+}
+
+fn get_fork(attrs: &[Attribute], ty: TokenStream, handle: TokenStream) -> TokenStream {
+    match priority(attrs) {
+        Some(priority) => quote!(channel.get_fork_with_priority::<#ty>(#handle, #priority)),
+        None => quote!(channel.get_fork::<#ty>(#handle)),
+    }
+}
+
 pub fn annotate(item: &mut ItemImpl) {
     let mut context = Context::new(&SHA256);
     context.update(item.clone().into_token_stream().to_string().as_bytes());
@@ -10,7 +10,7 @@ use quote::ToTokens;
 use syn::parse;
 use synstructure::decl_derive;
 
-decl_derive!([Kind, attributes(kind)] => kind::derive);
+decl_derive!([Kind, attributes(kind, priority)] => kind::derive);
 
 decl_derive!([Share] => share::derive);
 
@@ -2,7 +2,7 @@ use proc_macro2::TokenStream;
 use quote::{format_ident, quote, quote_spanned, ToTokens};
 use syn::{
     parse_quote, punctuated::Punctuated, spanned::Spanned, FnArg, GenericParam, ItemTrait, PatType,
-    Receiver, ReturnType, Token, TraitItem, TypeParamBound,
+    Path, Receiver, ReturnType, Token, TraitItem, TypeParamBound,
 };
 
 type MethodIndex = u8;
@@ -46,6 +46,23 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
     let mut from_fields = TokenStream::new();
     let mut shim_items = TokenStream::new();
     let mut reflected_items = TokenStream::new();
+    let mut pipelined_items = TokenStream::new();
+    let mut dispatch_arms = TokenStream::new();
+    let boxed_receiver: PatType =
+        if let FnArg::Typed(ty) = parse_quote!(self: DERIVE_alloc::boxed::Box<Self>) {
+            ty
+        } else {
+            panic!("could not parse hard-coded move receiver")
+        };
+    // Objects are never retained for pipelined calls if a method takes them by value, as the
+    // retained handle would keep them from being moved.
+    let moves = item.items.iter().any(|item| match item {
+        TraitItem::Method(method) => method.sig.inputs.iter().any(|input| match input {
+            FnArg::Typed(ty) => ty == &boxed_receiver,
+            _ => false,
+        }),
+        _ => false,
+    });
     for item in &item.items {
         use TraitItem::{Method, Type};
         if let Type(_) = item {
@@ -61,12 +78,6 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
             let mut receiver = None;
             let mut args = TokenStream::new();
             let inputs = &method.sig.inputs;
-            let boxed_receiver: PatType =
-                if let FnArg::Typed(ty) = parse_quote!(self: DERIVE_alloc::boxed::Box<Self>) {
-                    ty
-                } else {
-                    panic!("could not parse hard-coded move receiver")
-                };
             for input in inputs {
                 use FnArg::{Receiver, Typed};
                 if let Typed(ty) = input {
@@ -99,7 +110,14 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
                     .unwrap());
                 ty = quote!(FnOnce(#args));
             }
+            // Each method is carried on a fork of its own, so a priority given to the method
+            // applies to its calls and to everything passed to and returned from them.
+            let priority = method
+                .attrs
+                .iter()
+                .filter(|attr| attr.path.is_ident("priority"));
             fields.extend(quote! {
+                #(#priority)*
                 #mident: DERIVE_alloc::boxed::Box<dyn #ty #output + Send + Sync>,
             });
             let inputs: Punctuated<_, Token![,]> = inputs
@@ -117,7 +135,7 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
                 })
                 .collect();
             from_fields.extend(quote! {
-                #mident: { let object = object.clone(); DERIVE_alloc::boxed::Box::new(move |#inputs| #lock.#mident(#inputs)) },
+                #mident: { let object = object.clone(); let guard = guard.clone(); DERIVE_alloc::boxed::Box::new(move |#inputs| { let _ = &guard; #lock.#mident(#inputs) }) },
             });
             shim_items.extend(quote! {
                 #sig {
@@ -135,6 +153,58 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
                 quote!(call_move)
             };
             let arg_idents: Vec<_> = inputs.iter().map(|arg| arg.clone()).collect();
+            let output_ty = match output {
+                ReturnType::Type(_, ty) => ty.into_token_stream(),
+                ReturnType::Default => quote!(()),
+            };
+            let pipe_idx = idx as MethodIndex;
+            if receiver.is_mutable().is_some() {
+                pipelined_items.extend(quote! {
+                    #sig {
+                        let piped = if <dyn #ident<#params> as ::vessels::reflect::Reflected>::MOVES {
+                            Err((#(#arg_idents,)*))
+                        } else {
+                            ::vessels::kind::pipeline::Pipe::call::<(#(#arg_types,)*), #output_ty>(&self.pipe, #pipe_idx, (#(#arg_idents,)*))
+                        };
+                        match piped {
+                            Ok(output) => output,
+                            Err((#(#arg_idents,)*)) => {
+                                let object = self.object.clone();
+                                <#output_ty as ::vessels::kind::Flatten>::flatten(async move {
+                                    let object = object.await?;
+                                    let output = object.lock().unwrap().#mident(#inputs);
+                                    Ok::<_, ::vessels::kind::pipeline::PipelineError>(output)
+                                })
+                            }
+                        }
+                    }
+                });
+                dispatch_arms.extend(quote! {
+                    #pipe_idx => {
+                        let object = self.0.clone();
+                        let arguments = channel.get_fork::<(#(#arg_types,)*)>(arguments);
+                        DERIVE_alloc::boxed::Box::pin(async move {
+                            let (#(#arg_idents,)*) = arguments.await.map_err(::vessels::kind::pipeline::PipelineError::new)?;
+                            let object = object.await?;
+                            let output = object.lock().unwrap().#mident(#inputs);
+                            Ok(::vessels::kind::pipeline::Retained::new(output))
+                        })
+                    },
+                });
+            } else {
+                pipelined_items.extend(quote! {
+                    #sig {
+                        let object = self.object;
+                        <#output_ty as ::vessels::kind::Flatten>::flatten(async move {
+                            // Other handles to the object may outlive this call, so it is taken
+                            // from them rather than unwrapped, calls through them failing.
+                            let moved = DERIVE_alloc::boxed::Box::new(_DERIVED_Pipelined::new(::vessels::futures::future::err::<DERIVE_alloc::boxed::Box<dyn #ident<#params>>, _>(::vessels::kind::pipeline::PipelineError::moved()), ::vessels::kind::pipeline::Direct)) as DERIVE_alloc::boxed::Box<dyn #ident<#params>>;
+                            let object = ::core::mem::replace(&mut *object.await?.lock().unwrap(), moved);
+                            Ok::<_, ::vessels::kind::pipeline::PipelineError>(object.#mident(#inputs))
+                        })
+                    }
+                });
+            }
             reflected_items.extend(quote! {
                 #sig {
                     *DERIVE_alloc::boxed::Box::<dyn ::core::any::Any>::downcast(::vessels::reflect::Trait::<dyn #ident<#params>>::#call_method(self, #idx as ::vessels::reflect::MethodIndex, vec![#( DERIVE_alloc::boxed::Box::new(#arg_idents) as DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync> ),*]).unwrap()).unwrap()
@@ -240,6 +310,9 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
     let mut upcast_arms = TokenStream::new();
     let mut supertrait_ids = TokenStream::new();
     let mut derive_param_bounds = TokenStream::new();
+    let mut pipelined_fields = TokenStream::new();
+    let mut pipelined_from_fields = TokenStream::new();
+    let mut supertrait_moves = TokenStream::new();
     for (idx, supertrait) in item.supertraits.iter().enumerate() {
         use TypeParamBound::Trait;
         if let Trait(supertrait) = supertrait {
@@ -248,52 +321,37 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
             fields.extend(quote! {
                 #id: DERIVE_alloc::sync::Arc<::std::sync::Mutex<DERIVE_alloc::boxed::Box<<dyn #path as ::vessels::reflect::Reflected>::Shim>>>,
             });
-            supertrait_impls.extend(quote! {
-                impl<#kind_bounded_params> ::vessels::reflect::Trait<dyn #path> for _DERIVED_Shim<#params> {
-                    fn call(&self, index: ::vessels::reflect::MethodIndex, mut args: DERIVE_alloc::vec::Vec<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>>) -> ::core::result::Result<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>, ::vessels::reflect::CallError> {
-                        ::vessels::reflect::Trait::<dyn #path>::call(self.#id.lock().unwrap().as_ref() as &dyn #path, index, args)
-                    }
-                    fn call_mut(&mut self, index: ::vessels::reflect::MethodIndex, mut args: DERIVE_alloc::vec::Vec<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>>) -> ::core::result::Result<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>, ::vessels::reflect::CallError> {
-                        ::vessels::reflect::Trait::<dyn #path>::call_mut(self.#id.lock().unwrap().as_mut() as &mut dyn #path, index, args)
-                    }
-                    fn call_move(self: DERIVE_alloc::boxed::Box<Self>, index: ::vessels::reflect::MethodIndex, mut args: DERIVE_alloc::vec::Vec<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>>) -> ::core::result::Result<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>, ::vessels::reflect::CallError> {
-                        ::vessels::reflect::Trait::<dyn #path>::call_move(DERIVE_alloc::sync::Arc::try_unwrap(self.#id).map_err(|_| panic!("arc is not held exclusively")).unwrap().into_inner().unwrap() as DERIVE_alloc::boxed::Box<dyn #path>, index, args)
-                    }
-                    fn by_name(&self, name: &'_ str) -> ::core::result::Result<::vessels::reflect::MethodIndex, ::vessels::reflect::NameError> {
-                        ::vessels::reflect::Trait::<dyn #path>::by_name(self.#id.lock().unwrap().as_ref() as &dyn #path, name)
-                    }
-                    fn count(&self) -> ::vessels::reflect::MethodIndex {
-                        ::vessels::reflect::Trait::<dyn #path>::count(self.#id.lock().unwrap().as_ref() as &dyn #path)
-                    }
-                    fn name_of(&self, index: ::vessels::reflect::MethodIndex) -> ::core::result::Result<DERIVE_alloc::string::String, ::vessels::reflect::OutOfRangeError> {
-                        ::vessels::reflect::Trait::<dyn #path>::name_of(self.#id.lock().unwrap().as_ref() as &dyn #path, index)
-                    }
-                    fn types(&self, index: ::vessels::reflect::MethodIndex) -> ::core::result::Result<::vessels::reflect::MethodTypes, ::vessels::reflect::OutOfRangeError> {
-                        ::vessels::reflect::Trait::<dyn #path>::types(self.#id.lock().unwrap().as_ref() as &dyn #path, index)
-                    }
-                    fn this(&self) -> ::core::any::TypeId {
-                        ::vessels::reflect::Trait::<dyn #path>::this(self.#id.lock().unwrap().as_ref() as &dyn #path)
-                    }
-                    fn name(&self) -> DERIVE_alloc::string::String {
-                        ::vessels::reflect::Trait::<dyn #path>::name(self.#id.lock().unwrap().as_ref() as &dyn #path)
-                    }
-                    fn supertraits(&self) -> DERIVE_alloc::vec::Vec<::core::any::TypeId> {
-                        ::vessels::reflect::Trait::<dyn #path>::supertraits(self.#id.lock().unwrap().as_ref() as &dyn #path)
-                    }
-                    fn upcast_erased(self: DERIVE_alloc::boxed::Box<Self>, ty: ::core::any::TypeId) -> ::core::result::Result<DERIVE_alloc::boxed::Box<dyn ::vessels::reflect::Erased>, ::vessels::reflect::CastError> {
-                        ::vessels::reflect::Trait::<dyn #path>::upcast_erased(DERIVE_alloc::sync::Arc::try_unwrap(self.#id).map_err(|_| panic!("arc is not held exclusively")).unwrap().into_inner().unwrap() as DERIVE_alloc::boxed::Box<dyn #path>, ty)
-                    }
-                    fn erase(self: DERIVE_alloc::boxed::Box<Self>) -> DERIVE_alloc::boxed::Box<dyn ::vessels::reflect::Erased> {
-                        ::vessels::reflect::Trait::<dyn #path>::erase(DERIVE_alloc::sync::Arc::try_unwrap(self.#id).map_err(|_| panic!("arc is not held exclusively")).unwrap().into_inner().unwrap() as DERIVE_alloc::boxed::Box<dyn #path>)
-                    }
-                }
+            supertrait_impls.extend(supertrait_impl(
+                quote!(_DERIVED_Shim<#params>),
+                &kind_bounded_params,
+                &path,
+                quote!(self.#id.lock().unwrap().as_ref()),
+                quote!(self.#id.lock().unwrap().as_mut()),
+                quote!(DERIVE_alloc::sync::Arc::try_unwrap(self.#id).map_err(|_| panic!("arc is not held exclusively")).unwrap().into_inner().unwrap()),
+            ));
+            supertrait_impls.extend(supertrait_impl(
+                quote!(_DERIVED_Pipelined<DERIVEPIPE, #params>),
+                &quote!(DERIVEPIPE: ::vessels::kind::pipeline::Pipe, #kind_bounded_params),
+                &path,
+                quote!(self.#id.as_ref()),
+                quote!(self.#id.as_mut()),
+                quote!(self.#id),
+            ));
+            pipelined_fields.extend(quote! {
+                #id: DERIVE_alloc::boxed::Box<dyn #path>,
+            });
+            pipelined_from_fields.extend(quote! {
+                #id: <DERIVE_alloc::boxed::Box<dyn #path> as ::vessels::kind::Flatten>::flatten(object.clone().map_ok(|object| DERIVE_alloc::boxed::Box::new(<dyn #path as ::vessels::reflect::Reflected>::Shim::from_instance(object)) as DERIVE_alloc::boxed::Box<dyn #path>)),
             });
             from_fields.extend(quote! {
-                #id: DERIVE_alloc::sync::Arc::new(::std::sync::Mutex::new(DERIVE_alloc::boxed::Box::new(<dyn #path as ::vessels::reflect::Reflected>::Shim::from_instance(object)))),
+                #id: DERIVE_alloc::sync::Arc::new(::std::sync::Mutex::new(DERIVE_alloc::boxed::Box::new(<dyn #path as ::vessels::reflect::Reflected>::Shim::from_guarded(object.clone(), guard.clone())))),
             });
             derive_param_bounds.extend(quote! {
                 + #path
             });
+            supertrait_moves.extend(quote! {
+                || <dyn #path as ::vessels::reflect::Reflected>::MOVES
+            });
             supertrait_ids.extend(quote! {
                 ::core::any::TypeId::of::<dyn #path>(),
             });
@@ -304,6 +362,12 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
             })
         }
     }
+    for item in &mut item.items {
+        if let TraitItem::Method(method) = item {
+            method.attrs.retain(|attr| !attr.path.is_ident("priority"));
+        }
+    }
+    item.supertraits.push(parse_quote!(::vessels::reflect::Concrete));
     item.supertraits.push(parse_quote!(::core::marker::Send));
     item.supertraits.push(parse_quote!(::core::marker::Sync));
     let name = ident.to_string();
@@ -316,12 +380,17 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
             #[derive(::vessels::Kind)]
             #vis struct _DERIVED_Shim<#kind_bounded_params> {
                 #fields
+                _DERIVED_reference: Option<::vessels::core::handoff::Reference>,
                 _marker: ::core::marker::PhantomData<(#params)>
             }
             impl<#kind_bounded_params> _DERIVED_Shim<#params> {
                 #vis fn from_instance<DERIVEPARAM: ?Sized + #ident<#params> + 'static>(object: DERIVE_alloc::sync::Arc<::std::sync::Mutex<DERIVE_alloc::boxed::Box<DERIVEPARAM>>>) -> Self {
+                    Self::from_guarded(object, None)
+                }
+                #vis fn from_guarded<DERIVEPARAM: ?Sized + #ident<#params> + 'static>(object: DERIVE_alloc::sync::Arc<::std::sync::Mutex<DERIVE_alloc::boxed::Box<DERIVEPARAM>>>, guard: Option<DERIVE_alloc::sync::Arc<dyn ::core::any::Any + Sync + Send>>) -> Self {
                     _DERIVED_Shim {
                        #from_fields
+                       _DERIVED_reference: None,
                        _marker: ::core::marker::PhantomData
                     }
                 }
@@ -337,6 +406,12 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
                 type ErasedShim = _DERIVED_ErasedShim<#params>;
                 #[doc(hidden)]
                 const DO_NOT_IMPLEMENT_THIS_MARKER_TRAIT_MANUALLY: () = ();
+                #[doc(hidden)]
+                const MOVES: bool = #moves #supertrait_moves;
+                #[doc(hidden)]
+                fn share(object: DERIVE_alloc::sync::Arc<::std::sync::Mutex<DERIVE_alloc::boxed::Box<Self>>>, guard: DERIVE_alloc::sync::Arc<dyn ::core::any::Any + Sync + Send>) -> DERIVE_alloc::boxed::Box<Self> {
+                    DERIVE_alloc::boxed::Box::new(_DERIVED_Shim::from_guarded(object, Some(guard))) as DERIVE_alloc::boxed::Box<dyn #ident<#params>>
+                }
             }
             impl<#kind_bounded_params> From<DERIVE_alloc::boxed::Box<dyn #ident<#params>>> for _DERIVED_ErasedShim<#params> {
                 fn from(input: DERIVE_alloc::boxed::Box<dyn #ident<#params>>) -> _DERIVED_ErasedShim<#params> {
@@ -478,9 +553,58 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
                     DERIVE_alloc::boxed::Box::new(_DERIVED_ErasedShim::from(self)) as DERIVE_alloc::boxed::Box<dyn ::vessels::reflect::Erased>
                 }
             }
+            struct _DERIVED_Pipelined<DERIVEPIPE: ::vessels::kind::pipeline::Pipe, #kind_bounded_params> {
+                pipe: DERIVEPIPE,
+                object: ::vessels::futures::future::Shared<::vessels::kind::Fallible<DERIVE_alloc::sync::Arc<::std::sync::Mutex<DERIVE_alloc::boxed::Box<dyn #ident<#params>>>>, ::vessels::kind::pipeline::PipelineError>>,
+                #pipelined_fields
+            }
+            impl<DERIVEPIPE: ::vessels::kind::pipeline::Pipe, #kind_bounded_params> _DERIVED_Pipelined<DERIVEPIPE, #params> {
+                fn new<DERIVEERROR: 'static + Sync + Send + Into<::vessels::anyhow::Error>, DERIVEFUTURE: ::core::future::Future<Output = ::core::result::Result<DERIVE_alloc::boxed::Box<dyn #ident<#params>>, DERIVEERROR>> + Sync + Send + 'static>(future: DERIVEFUTURE, pipe: DERIVEPIPE) -> Self {
+                    use ::vessels::futures::{FutureExt, TryFutureExt};
+                    let object = (DERIVE_alloc::boxed::Box::pin(future.map_ok(|object| DERIVE_alloc::sync::Arc::new(::std::sync::Mutex::new(object))).map_err(::vessels::kind::pipeline::PipelineError::new)) as ::vessels::kind::Fallible<_, _>).shared();
+                    ::vessels::core::spawn(object.clone().map(|_| ()));
+                    _DERIVED_Pipelined {
+                        pipe,
+                        #pipelined_from_fields
+                        object,
+                    }
+                }
+            }
+            impl<DERIVEPIPE: ::vessels::kind::pipeline::Pipe, #kind_bounded_params> #ident<#params> for _DERIVED_Pipelined<DERIVEPIPE, #params> {
+                #pipelined_items
+            }
+            struct _DERIVED_Dispatch<#kind_bounded_params>(::vessels::futures::future::Shared<::vessels::kind::Fallible<DERIVE_alloc::sync::Arc<::std::sync::Mutex<DERIVE_alloc::boxed::Box<dyn #ident<#params>>>>, ::vessels::kind::pipeline::PipelineError>>);
+            impl<DERIVECHANNEL: ::vessels::channel::Fork, #kind_bounded_params> ::vessels::kind::pipeline::Dispatch<DERIVECHANNEL> for _DERIVED_Dispatch<#params> {
+                fn dispatch(&self, method: ::vessels::reflect::MethodIndex, arguments: ::vessels::channel::ForkHandle, channel: &DERIVECHANNEL) -> ::vessels::kind::Fallible<::vessels::kind::pipeline::Retained<DERIVECHANNEL>, ::vessels::kind::pipeline::PipelineError> {
+                    match method {
+                        #dispatch_arms
+                        _ => DERIVE_alloc::boxed::Box::pin(::vessels::futures::future::ready(Err(::vessels::kind::pipeline::PipelineError::new(::vessels::reflect::CallError::OutOfRange(::vessels::reflect::OutOfRangeError {
+                            index: method,
+                        }))))),
+                    }
+                }
+            }
+            impl<#kind_bounded_params> ::vessels::kind::Flatten for DERIVE_alloc::boxed::Box<dyn #ident<#params>> {
+                fn flatten<DERIVEERROR: 'static + Sync + Send + Into<::vessels::anyhow::Error>, DERIVEFUTURE: ::core::future::Future<Output = ::core::result::Result<Self, DERIVEERROR>> + Sync + Send + 'static>(future: DERIVEFUTURE) -> Self {
+                    Self::flatten_pipelined(future, ::vessels::kind::pipeline::Direct)
+                }
+                fn flatten_pipelined<DERIVEERROR: 'static + Sync + Send + Into<::vessels::anyhow::Error>, DERIVEFUTURE: ::core::future::Future<Output = ::core::result::Result<Self, DERIVEERROR>> + Sync + Send + 'static, DERIVEPIPE: ::vessels::kind::pipeline::Pipe>(future: DERIVEFUTURE, pipe: DERIVEPIPE) -> Self {
+                    DERIVE_alloc::boxed::Box::new(_DERIVED_Pipelined::new(future, pipe))
+                }
+                #[doc(hidden)]
+                fn retain<DERIVECHANNEL: ::vessels::channel::Fork>(self) -> (Self, Option<DERIVE_alloc::boxed::Box<dyn ::vessels::kind::pipeline::Dispatch<DERIVECHANNEL>>>) {
+                    use ::vessels::futures::FutureExt;
+                    if <dyn #ident<#params> as ::vessels::reflect::Reflected>::MOVES {
+                        return (self, None);
+                    }
+                    let object = DERIVE_alloc::sync::Arc::new(::std::sync::Mutex::new(self));
+                    let target = (DERIVE_alloc::boxed::Box::pin(::vessels::futures::future::ok(object.clone())) as ::vessels::kind::Fallible<_, _>).shared();
+                    (<dyn #ident<#params> as ::vessels::reflect::Reflected>::share(object, DERIVE_alloc::sync::Arc::new(())), Some(DERIVE_alloc::boxed::Box::new(_DERIVED_Dispatch(target))))
+                }
+            }
             #[::vessels::kind]
             impl<#kind_bounded_params> ::vessels::Kind for DERIVE_alloc::boxed::Box<dyn #ident<#params>> {
-                type ConstructItem = ::vessels::channel::ForkHandle;
+                type ConstructItem = (::vessels::channel::ForkHandle, bool);
                 type ConstructError = ::vessels::void::Void;
                 type ConstructFuture = ::vessels::kind::Future<::vessels::kind::ConstructResult<Self>>;
                 type DeconstructItem = ();
@@ -493,21 +617,127 @@ pub fn build(_: TokenStream, item: &mut ItemTrait) -> TokenStream {
                 ) -> <Self as ::vessels::Kind>::DeconstructFuture {
                     use ::vessels::futures::{SinkExt, TryFutureExt};
                     DERIVE_alloc::boxed::Box::pin(async move {
-                        channel.send(channel.fork::<_DERIVED_Shim<#params>>(_DERIVED_Shim::from_instance(DERIVE_alloc::sync::Arc::new(::std::sync::Mutex::new(self)))).await.unwrap()).unwrap_or_else(|_| panic!("arc is not held exclusively")).await;
+                        // Only a proxy received from elsewhere carries a reference to hand off.
+                        let reference = <dyn #ident<#params> as ::vessels::reflect::Concrete>::concrete(self.as_ref())
+                            .downcast_ref::<_DERIVED_Shim<#params>>()
+                            .and_then(|shim| shim._DERIVED_reference.clone());
+                        let object = DERIVE_alloc::sync::Arc::new(::std::sync::Mutex::new(self));
+                        let (shim, forwarded) = match reference {
+                            Some(reference) => {
+                                let forwarded = !::vessels::core::handoff::exported(&reference);
+                                let mut shim = _DERIVED_Shim::from_instance(object);
+                                shim._DERIVED_reference = Some(reference);
+                                (shim, forwarded)
+                            }
+                            None => {
+                                let share = object.clone();
+                                let export = ::vessels::core::handoff::export(move |guard, reference| {
+                                    let mut shim = _DERIVED_Shim::<#params>::from_guarded(share.clone(), Some(guard));
+                                    shim._DERIVED_reference = Some(reference);
+                                    DERIVE_alloc::boxed::Box::new(shim) as DERIVE_alloc::boxed::Box<dyn #ident<#params>>
+                                });
+                                match export.await {
+                                    Some((reference, guard)) => {
+                                        let mut shim = _DERIVED_Shim::from_guarded(object, Some(guard));
+                                        shim._DERIVED_reference = Some(reference);
+                                        (shim, false)
+                                    }
+                                    None => (_DERIVED_Shim::from_instance(object), false),
+                                }
+                            }
+                        };
+                        channel.send((channel.fork::<_DERIVED_Shim<#params>>(shim).await.unwrap(), forwarded)).unwrap_or_else(|_| panic!("arc is not held exclusively")).await;
                         Ok(())
                     })
                 }
 
+                fn pipeline<DERIVEPIPE: ::vessels::kind::pipeline::Pipe>(
+                    object: ::vessels::kind::Fallible<Self, ::vessels::kind::pipeline::PipelineError>,
+                    pipe: DERIVEPIPE,
+                ) -> ::core::result::Result<Self, ::vessels::kind::Fallible<Self, ::vessels::kind::pipeline::PipelineError>> {
+                    Ok(<Self as ::vessels::kind::Flatten>::flatten_pipelined(object, pipe))
+                }
+
+                fn retain_pending<DERIVECHANNEL: ::vessels::channel::Fork>(
+                    object: ::vessels::kind::Fallible<Self, ::vessels::kind::pipeline::PipelineError>,
+                ) -> (::vessels::kind::Fallible<Self, ::vessels::kind::pipeline::PipelineError>, Option<DERIVE_alloc::boxed::Box<dyn ::vessels::kind::pipeline::Dispatch<DERIVECHANNEL>>>) {
+                    use ::vessels::futures::{FutureExt, TryFutureExt};
+                    if <dyn #ident<#params> as ::vessels::reflect::Reflected>::MOVES {
+                        return (object, None);
+                    }
+                    let target = (DERIVE_alloc::boxed::Box::pin(object.map_ok(|object| DERIVE_alloc::sync::Arc::new(::std::sync::Mutex::new(object)))) as ::vessels::kind::Fallible<_, _>).shared();
+                    let object = target.clone().map_ok(|object| <dyn #ident<#params> as ::vessels::reflect::Reflected>::share(object, DERIVE_alloc::sync::Arc::new(())));
+                    (DERIVE_alloc::boxed::Box::pin(object), Some(DERIVE_alloc::boxed::Box::new(_DERIVED_Dispatch(target))))
+                }
+
                 fn construct<C: ::vessels::channel::Channel<<Self as ::vessels::Kind>::ConstructItem, <Self as ::vessels::Kind>::DeconstructItem>>(
                     mut channel: C,
                 ) -> <Self as ::vessels::Kind>::ConstructFuture {
                     use ::vessels::futures::StreamExt;
                     DERIVE_alloc::boxed::Box::pin(async move {
-                        let handle = channel.next().await.unwrap();
-                        Ok(DERIVE_alloc::boxed::Box::new(channel.get_fork::<_DERIVED_Shim<#params>>(handle).await.unwrap()) as DERIVE_alloc::boxed::Box<dyn #ident<#params>>)
+                        let (handle, forwarded) = channel.next().await.unwrap();
+                        let shim = channel.get_fork::<_DERIVED_Shim<#params>>(handle).await.unwrap();
+                        if forwarded {
+                            if let Some(reference) = shim._DERIVED_reference.clone() {
+                                if let Ok(object) = ::vessels::core::handoff::resolve::<DERIVE_alloc::boxed::Box<dyn #ident<#params>>>(reference).await {
+                                    return Ok(object);
+                                }
+                            }
+                        }
+                        Ok(DERIVE_alloc::boxed::Box::new(shim) as DERIVE_alloc::boxed::Box<dyn #ident<#params>>)
                     })
                 }
             }
         };
     }
 }
+
+fn supertrait_impl(
+    ty: TokenStream,
+    bounded_params: &impl ToTokens,
+    path: &Path,
+    borrowed: TokenStream,
+    borrowed_mut: TokenStream,
+    owned: TokenStream,
+) -> TokenStream {
+    quote! {
+        impl<#bounded_params> ::vessels::reflect::Trait<dyn #path> for #ty {
+            fn call(&self, index: ::vessels::reflect::MethodIndex, mut args: DERIVE_alloc::vec::Vec<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>>) -> ::core::result::Result<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>, ::vessels::reflect::CallError> {
+                ::vessels::reflect::Trait::<dyn #path>::call(#borrowed as &dyn #path, index, args)
+            }
+            fn call_mut(&mut self, index: ::vessels::reflect::MethodIndex, mut args: DERIVE_alloc::vec::Vec<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>>) -> ::core::result::Result<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>, ::vessels::reflect::CallError> {
+                ::vessels::reflect::Trait::<dyn #path>::call_mut(#borrowed_mut as &mut dyn #path, index, args)
+            }
+            fn call_move(self: DERIVE_alloc::boxed::Box<Self>, index: ::vessels::reflect::MethodIndex, mut args: DERIVE_alloc::vec::Vec<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>>) -> ::core::result::Result<DERIVE_alloc::boxed::Box<dyn ::core::any::Any + Send + Sync>, ::vessels::reflect::CallError> {
+                ::vessels::reflect::Trait::<dyn #path>::call_move(#owned as DERIVE_alloc::boxed::Box<dyn #path>, index, args)
+            }
+            fn by_name(&self, name: &'_ str) -> ::core::result::Result<::vessels::reflect::MethodIndex, ::vessels::reflect::NameError> {
+                ::vessels::reflect::Trait::<dyn #path>::by_name(#borrowed as &dyn #path, name)
+            }
+            fn count(&self) -> ::vessels::reflect::MethodIndex {
+                ::vessels::reflect::Trait::<dyn #path>::count(#borrowed as &dyn #path)
+            }
+            fn name_of(&self, index: ::vessels::reflect::MethodIndex) -> ::core::result::Result<DERIVE_alloc::string::String, ::vessels::reflect::OutOfRangeError> {
+                ::vessels::reflect::Trait::<dyn #path>::name_of(#borrowed as &dyn #path, index)
+            }
+            fn types(&self, index: ::vessels::reflect::MethodIndex) -> ::core::result::Result<::vessels::reflect::MethodTypes, ::vessels::reflect::OutOfRangeError> {
+                ::vessels::reflect::Trait::<dyn #path>::types(#borrowed as &dyn #path, index)
+            }
+            fn this(&self) -> ::core::any::TypeId {
+                ::vessels::reflect::Trait::<dyn #path>::this(#borrowed as &dyn #path)
+            }
+            fn name(&self) -> DERIVE_alloc::string::String {
+                ::vessels::reflect::Trait::<dyn #path>::name(#borrowed as &dyn #path)
+            }
+            fn supertraits(&self) -> DERIVE_alloc::vec::Vec<::core::any::TypeId> {
+                ::vessels::reflect::Trait::<dyn #path>::supertraits(#borrowed as &dyn #path)
+            }
+            fn upcast_erased(self: DERIVE_alloc::boxed::Box<Self>, ty: ::core::any::TypeId) -> ::core::result::Result<DERIVE_alloc::boxed::Box<dyn ::vessels::reflect::Erased>, ::vessels::reflect::CastError> {
+                ::vessels::reflect::Trait::<dyn #path>::upcast_erased(#owned as DERIVE_alloc::boxed::Box<dyn #path>, ty)
+            }
+            fn erase(self: DERIVE_alloc::boxed::Box<Self>) -> DERIVE_alloc::boxed::Box<dyn ::vessels::reflect::Erased> {
+                ::vessels::reflect::Trait::<dyn #path>::erase(#owned as DERIVE_alloc::boxed::Box<dyn #path>)
+            }
+        }
+    }
+}
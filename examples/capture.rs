@@ -0,0 +1,36 @@
+use vessels::{
+    channel::IdChannel,
+    core::run,
+    format::{ApplyDecode, ApplyEncode, Capture, Cbor, Replay},
+    kind::Infallible,
+    log, OnTo,
+};
+
+use std::{env::temp_dir, fs::File};
+
+type Call = Box<dyn Fn(u32) -> Infallible<u32> + Send + Sync>;
+
+fn main() {
+    let path = temp_dir().join("vessels-capture.cbor");
+    let call: Call = Box::new(|n| Box::pin(async move { Ok(n * 2) }));
+
+    run(async move {
+        let encoded = call.on_to::<IdChannel>().await.encode::<Cbor>();
+        let captured = Capture::new::<Cbor>(encoded, File::create(&path).unwrap()).unwrap();
+        let flusher = captured.flusher();
+        let decoded: Call = captured.decode::<IdChannel, Cbor>().await.unwrap();
+        log!("live: {}", decoded(21).await.unwrap());
+        drop(decoded);
+        flusher.flush().await;
+
+        // The captured session is reproduced offline, without the original function.
+        let replay = Replay::read::<Cbor>(File::open(&path).unwrap()).unwrap();
+        log!("captured {} records", replay.records().len());
+        let replayed: Call = replay
+            .paced_connection()
+            .decode::<IdChannel, Cbor>()
+            .await
+            .unwrap();
+        log!("replayed: {}", replayed(21).await.unwrap());
+    });
+}
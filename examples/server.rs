@@ -1,16 +1,17 @@
 use vessels::{
     channel::IdChannel,
     core::{hal::network::Server, run},
-    format::Cbor,
+    format::Formats,
 };
 
 pub fn main() {
     run(async move {
         Server::new()
             .unwrap()
-            .listen::<String, IdChannel, Cbor>(
+            .listen_negotiated::<String, IdChannel>(
                 "127.0.0.1:61200".parse().unwrap(),
                 Box::new(move || Box::pin(async move { "format".to_string() })),
+                Formats::default(),
             )
             .await
             .unwrap();
@@ -1,12 +1,23 @@
 use alloc::sync::{Arc, Weak};
-use core::{any::TypeId, pin::Pin};
+use core::{
+    any::TypeId,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{Mutex, RwLock},
 };
 
-use crate::{channel::ForkHandle, Kind};
+use crate::{
+    channel::{BufferLimits, ForkHandle},
+    kind::TransportError,
+    Kind,
+};
+
+use super::{id::REGISTRY, Control, ForkCount, CONTROL};
 
+use anyhow::Error;
 use weak_table::PtrWeakHashSet;
 
 use futures::{
@@ -14,16 +25,51 @@ use futures::{
     Future,
 };
 
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn live() -> usize {
+    LIVE.load(Ordering::SeqCst)
+}
+
 struct ContextState {
     channel_types: HashMap<ForkHandle, TypeId>,
     unused_indices: Vec<ForkHandle>,
     next_index: ForkHandle,
+    released: HashSet<ForkHandle>,
+}
+
+impl ContextState {
+    fn new(next_index: ForkHandle) -> Self {
+        REGISTRY.add_type::<Control>();
+        let mut channel_types = HashMap::new();
+        channel_types.insert(CONTROL, TypeId::of::<Control>());
+        ContextState {
+            channel_types,
+            next_index,
+            unused_indices: vec![],
+            released: HashSet::new(),
+        }
+    }
+
+    fn insert(&mut self, handle: ForkHandle, ty: TypeId) {
+        if self.channel_types.insert(handle, ty).is_none() {
+            LIVE.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for ContextState {
+    fn drop(&mut self) {
+        LIVE.fetch_sub(self.channel_types.len() - 1, Ordering::SeqCst);
+    }
 }
 
 #[derive(Clone)]
 pub struct Context {
     state: Arc<RwLock<ContextState>>,
     tasks: Arc<Mutex<HashMap<ForkHandle, PtrWeakHashSet<Weak<AtomicWaker>>>>>,
+    failure: Arc<Mutex<Option<Arc<Error>>>>,
+    limits: BufferLimits,
 }
 
 pub(crate) struct WaitFor {
@@ -35,7 +81,7 @@ pub(crate) struct WaitFor {
 impl Drop for WaitFor {
     fn drop(&mut self) {
         let mut tasks = self.context.tasks.lock().unwrap();
-        if tasks.get(&self.id).unwrap().len() == 1 {
+        if tasks.get(&self.id).map_or(false, |tasks| tasks.len() <= 1) {
             tasks.remove(&self.id);
         }
     }
@@ -45,16 +91,13 @@ impl Future for WaitFor {
     type Output = ();
 
     fn poll(self: Pin<&mut Self>, cx: &mut FContext) -> Poll<Self::Output> {
+        self.task.register(cx.waker());
         let state = self.context.state.read().unwrap();
-        let output = state.channel_types.get(&self.id).map_or_else(
-            || {
-                self.task.register(cx.waker());
-                Poll::Pending
-            },
-            |_| Poll::Ready(()),
-        );
-        drop(state);
-        output
+        if state.channel_types.contains_key(&self.id) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
     }
 }
 
@@ -79,41 +122,40 @@ impl Context {
         state.channel_types.get(&id).cloned()
     }
 
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(limits: BufferLimits) -> Self {
         Context {
-            state: Arc::new(RwLock::new(ContextState {
-                channel_types: HashMap::new(),
-                next_index: ForkHandle(0),
-                unused_indices: vec![],
-            })),
+            state: Arc::new(RwLock::new(ContextState::new(ForkHandle(0)))),
             tasks: Arc::new(Mutex::new(HashMap::new())),
+            failure: Arc::new(Mutex::new(None)),
+            limits,
         }
     }
 
-    pub(crate) fn new_shim() -> Self {
+    pub(crate) fn new_shim(limits: BufferLimits) -> Self {
         Context {
-            state: Arc::new(RwLock::new(ContextState {
-                channel_types: HashMap::new(),
-                next_index: ForkHandle(1),
-                unused_indices: vec![],
-            })),
+            state: Arc::new(RwLock::new(ContextState::new(ForkHandle(1)))),
             tasks: Arc::new(Mutex::new(HashMap::new())),
+            failure: Arc::new(Mutex::new(None)),
+            limits,
         }
     }
 
+    pub(crate) fn limits(&self) -> BufferLimits {
+        self.limits
+    }
+
     pub(crate) fn create<K: Kind>(&self) -> ForkHandle {
         let mut state = self.state.write().unwrap();
         let tasks = self.tasks.lock().unwrap();
         let d = TypeId::of::<K::DeconstructItem>();
         let id = if let Some(id) = state.unused_indices.pop() {
-            state.channel_types.insert(id, d);
             id
         } else {
             let id = state.next_index;
             state.next_index = ForkHandle(state.next_index.0 + 2);
-            state.channel_types.insert(id, d);
             id
         };
+        state.insert(id, d);
         if let Some(tasks) = tasks.get(&id) {
             tasks.iter().for_each(|task| task.wake())
         }
@@ -124,9 +166,62 @@ impl Context {
         let tasks = self.tasks.lock().unwrap();
         let mut state = self.state.write().unwrap();
         let c = TypeId::of::<K::ConstructItem>();
-        state.channel_types.insert(handle, c);
+        state.insert(handle, c);
         if let Some(tasks) = tasks.get(&handle) {
             tasks.iter().for_each(|task| task.wake())
         }
     }
+
+    /// Whether `handle` was allocated by this side of the connection.
+    pub(crate) fn is_local(&self, handle: ForkHandle) -> bool {
+        let state = self.state.read().unwrap();
+        handle.0 % 2 == state.next_index.0 % 2
+    }
+
+    /// Records that one side of the connection has released `handle`. Once both sides have,
+    /// the handle is forgotten and, if it was allocated here, made available for reuse.
+    ///
+    /// The peer's release may arrive before items it sent on the fork have been delivered, and
+    /// so before the fork is established here, which is why unknown handles are recorded too.
+    pub(crate) fn release(&self, handle: ForkHandle) {
+        let mut state = self.state.write().unwrap();
+        if handle == CONTROL {
+            return;
+        }
+        if state.released.remove(&handle) {
+            if state.channel_types.remove(&handle).is_some() {
+                LIVE.fetch_sub(1, Ordering::SeqCst);
+            }
+            if handle.0 % 2 == state.next_index.0 % 2 {
+                state.unused_indices.push(handle);
+            }
+        } else {
+            state.released.insert(handle);
+        }
+    }
+
+    /// Records `cause` as the failure that ended the connection. Only the first is kept, as
+    /// later failures are generally consequences of it.
+    pub(crate) fn fail(&self, cause: Error) {
+        let mut failure = self.failure.lock().unwrap();
+        if failure.is_none() {
+            failure.replace(Arc::new(cause));
+        }
+    }
+
+    /// The error with which the forks of the connection report its loss.
+    pub(crate) fn lost(&self) -> TransportError {
+        match self.failure.lock().unwrap().as_ref() {
+            Some(cause) => TransportError::failed(cause.clone()),
+            None => TransportError::lost(),
+        }
+    }
+
+    pub(crate) fn count(&self) -> ForkCount {
+        let state = self.state.read().unwrap();
+        ForkCount {
+            live: state.channel_types.len() - 1,
+            free: state.unused_indices.len(),
+        }
+    }
 }
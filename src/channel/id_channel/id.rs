@@ -29,7 +29,7 @@ impl Registry {
         self.add_type::<K::DeconstructItem>();
     }
 
-    fn add_type<T: Serialize + DeserializeOwned + Sync + Send + 'static>(&self) {
+    pub(crate) fn add_type<T: Serialize + DeserializeOwned + Sync + Send + 'static>(&self) {
         if !self.items.read().unwrap().contains_key(&TypeId::of::<T>()) {
             let mut items = self.items.write().unwrap();
             if !items.contains_key(&TypeId::of::<T>()) {
@@ -63,11 +63,9 @@ impl<'de, 'a> DeserializeSeed<'de> for Id<'a> {
     {
         let mut deserializer = erased_serde::Deserializer::erase(deserializer);
         (REGISTRY
-            .get(
-                self.1
-                    .get(self.0)
-                    .ok_or(Error::custom(format!("ASYNC_WAIT {} ", (self.0).0)))?,
-            )
+            .get(self.1.get(self.0).ok_or_else(|| {
+                Error::custom(format!("underlying channel {} does not exist", self.0))
+            })?)
             .ok_or(Error::custom("no deserializer in registry"))?)(&mut deserializer)
         .map_err(Error::custom)
     }
@@ -3,17 +3,26 @@ use crate::SerdeAny;
 use super::{Context, Id};
 
 use serde::{
-    de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor},
+    de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor},
     ser::{SerializeMap, SerializeSeq, Serializer},
-    Serialize,
+    Deserialize, Serialize,
 };
 
-use crate::channel::ForkHandle;
+use crate::channel::{ForkHandle, Incoming};
 
 use core::fmt;
 
 pub struct Item(pub(crate) ForkHandle, pub(crate) Box<dyn SerdeAny>, Context);
 
+/// The handle reserved for messages about the lifecycle of other forks.
+pub(crate) const CONTROL: ForkHandle = ForkHandle(core::u32::MAX);
+
+#[derive(Serialize, Deserialize)]
+pub(crate) enum Control {
+    /// The sender will send nothing further on the fork.
+    Release(ForkHandle),
+}
+
 impl Serialize for Item {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -37,12 +46,22 @@ impl Item {
     pub(crate) fn new(channel: ForkHandle, content: Box<dyn SerdeAny>, context: Context) -> Self {
         Item(channel, content, context)
     }
+
+    /// A release is delivered in order with the items of the fork it releases rather than
+    /// with other control messages, so that it cannot overtake them while they are buffered.
+    fn incoming(self) -> Incoming<Self> {
+        let fork = match self.1.downcast_ref::<Control>() {
+            Some(Control::Release(handle)) if self.0 == CONTROL => *handle,
+            _ => self.0,
+        };
+        Incoming::Ready(fork, self)
+    }
 }
 
 struct ItemVisitor(Context);
 
 impl<'de> Visitor<'de> for ItemVisitor {
-    type Value = Item;
+    type Value = Incoming<Item>;
 
     fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(formatter, "a channel item")
@@ -55,10 +74,16 @@ impl<'de> Visitor<'de> for ItemVisitor {
         let channel = seq
             .next_element()?
             .ok_or_else(|| de::Error::invalid_length(0, &"two elements"))?;
+        if self.0.get(channel).is_none() {
+            // Formats that cannot skip untyped data leave it unread, the frame is read
+            // again in full once the channel is established.
+            let _ = seq.next_element::<IgnoredAny>();
+            return Ok(Incoming::Pending(channel));
+        }
         let data = seq
             .next_element_seed(Id::new(channel, &mut self.0))?
             .ok_or_else(|| de::Error::invalid_length(1, &"two elements"))?;
-        Ok(Item(channel, data, self.0))
+        Ok(Item(channel, data, self.0).incoming())
     }
 
     fn visit_map<A>(mut self, mut map: A) -> Result<Self::Value, A::Error>
@@ -67,6 +92,7 @@ impl<'de> Visitor<'de> for ItemVisitor {
     {
         let mut channel = None;
         let mut data = None;
+        let mut pending = false;
         while let Some(key) = map.next_key::<String>()? {
             match key.as_ref() {
                 "channel" => {
@@ -76,10 +102,17 @@ impl<'de> Visitor<'de> for ItemVisitor {
                     channel = Some(map.next_value()?);
                 }
                 "data" => {
-                    if data.is_some() {
+                    if data.is_some() || pending {
                         return Err(serde::de::Error::duplicate_field("data"));
                     }
-                    data = Some(map.next_value_seed(Id::new(channel.unwrap(), &mut self.0))?);
+                    let channel =
+                        channel.ok_or_else(|| serde::de::Error::missing_field("channel"))?;
+                    if self.0.get(channel).is_none() {
+                        map.next_value::<IgnoredAny>()?;
+                        pending = true;
+                    } else {
+                        data = Some(map.next_value_seed(Id::new(channel, &mut self.0))?);
+                    }
                 }
                 name => {
                     return Err(de::Error::unknown_field(name, &["data", "channel"]));
@@ -87,15 +120,18 @@ impl<'de> Visitor<'de> for ItemVisitor {
             }
         }
         let channel = channel.ok_or_else(|| serde::de::Error::missing_field("channel"))?;
+        if pending {
+            return Ok(Incoming::Pending(channel));
+        }
         let data = data.ok_or_else(|| serde::de::Error::missing_field("data"))?;
-        Ok(Item(channel, data, self.0))
+        Ok(Item(channel, data, self.0).incoming())
     }
 }
 
 impl<'de> DeserializeSeed<'de> for Context {
-    type Value = Item;
+    type Value = Incoming<Item>;
 
-    fn deserialize<D>(self, deserializer: D) -> Result<Item, D::Error>
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
         D: Deserializer<'de>,
     {
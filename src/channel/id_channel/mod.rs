@@ -2,47 +2,89 @@ mod context;
 pub(crate) use context::Context;
 mod item;
 pub use item::Item;
+pub(crate) use item::{Control, CONTROL};
 mod id;
 pub(crate) use id::Id;
 use id::REGISTRY;
+mod schedule;
+use schedule::Outgoing;
 
 use alloc::sync::Arc;
+use anyhow::Error;
 use core::{
     fmt::{self, Display, Formatter},
     marker::PhantomData,
     pin::Pin,
 };
 use futures::{
-    channel::mpsc::{unbounded, SendError, UnboundedReceiver, UnboundedSender},
-    future::ok,
+    channel::{
+        mpsc::{channel, Receiver, SendError, Sender},
+        oneshot,
+    },
+    future::{ok, ready, select, Either},
+    ready,
     task::{Context as FContext, Poll},
-    Future as IFuture, FutureExt, Sink as ISink, SinkExt, Stream, StreamExt, TryFutureExt,
+    Future as IFuture, Sink as ISink, SinkExt, Stream, StreamExt, TryFutureExt,
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
 use thiserror::Error;
 
 use crate::{
-    channel::{Channel, Context as IContext, Fork as IFork, ForkHandle, Waiter},
+    channel::{
+        BufferLimits, Channel, Context as IContext, Fork as IFork, ForkHandle, Priority, Waiter,
+    },
     core::spawn,
-    kind::{Fallible, Future, Sink},
-    Kind, SerdeAny, Target,
+    kind::{Fallible, Future, Sink, TransportError},
+    ErrorBound, Kind, SerdeAny, Target,
 };
 
 use super::{ChannelError, Shim as IShim};
 
+/// The number of forks on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForkCount {
+    /// Forks that have not yet been released by both sides of the connection.
+    pub live: usize,
+    /// Handles released by both sides that are waiting to be reused.
+    pub free: usize,
+}
+
+/// Reports on the forks of an `IdChannel`. This remains usable after the channel is encoded.
+#[derive(Clone)]
+pub struct Forks(Context);
+
+impl Forks {
+    pub fn count(&self) -> ForkCount {
+        self.0.count()
+    }
+}
+
+/// Returns the number of live forks across every `IdChannel` in this process.
+pub fn live_forks() -> usize {
+    context::live()
+}
+
 pub struct IdChannel {
-    out_channel: (
-        Pin<Box<UnboundedReceiver<Item>>>,
-        Pin<Box<UnboundedSender<Item>>>,
-    ),
+    outgoing: Outgoing,
     context: Context,
     in_channels: Arc<Mutex<HashMap<ForkHandle, Sink<Box<dyn SerdeAny>, ChannelError>>>>,
+    pending: HashMap<ForkHandle, VecDeque<Box<dyn SerdeAny>>>,
+    pending_count: usize,
+    /// The inputs of forks released by the peer, with the items still to be delivered to them
+    /// before they are closed.
+    closing: Vec<(
+        Sink<Box<dyn SerdeAny>, ChannelError>,
+        VecDeque<Box<dyn SerdeAny>>,
+    )>,
 }
 
 #[derive(Clone)]
 struct IdChannelHandle {
-    out_channel: Pin<Box<UnboundedSender<Item>>>,
+    outgoing: Outgoing,
     context: Context,
     in_channels: Arc<Mutex<HashMap<ForkHandle, Sink<Box<dyn SerdeAny>, ChannelError>>>>,
 }
@@ -57,7 +99,7 @@ impl Stream for IdChannel {
     type Item = Item;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut FContext) -> Poll<Option<Self::Item>> {
-        self.out_channel.0.as_mut().poll_next(cx)
+        Pin::new(&mut self.outgoing).poll_next(cx)
     }
 }
 
@@ -90,6 +132,8 @@ pub enum IdChannelError {
     Channel(SinkStage, ForkHandle, ChannelError),
     #[error("underlying channel {0} does not exist")]
     InvalidId(ForkHandle),
+    #[error("item on underlying channel {0} does not have the expected type")]
+    UnexpectedType(ForkHandle),
 }
 
 impl Drop for IdChannel {
@@ -98,80 +142,176 @@ impl Drop for IdChannel {
     }
 }
 
+/// Delivers as much of `queue` to `channel` as it will accept, returning whether the channel
+/// has failed.
+fn deliver(
+    channel: &mut Sink<Box<dyn SerdeAny>, ChannelError>,
+    queue: &mut VecDeque<Box<dyn SerdeAny>>,
+    pending_count: &mut usize,
+    cx: &mut FContext,
+) -> bool {
+    while !queue.is_empty() {
+        match channel.as_mut().poll_ready(cx) {
+            Poll::Ready(Ok(())) => {
+                *pending_count -= 1;
+                if channel
+                    .as_mut()
+                    .start_send(queue.pop_front().unwrap())
+                    .is_err()
+                {
+                    return true;
+                }
+            }
+            Poll::Ready(Err(_)) => return true,
+            Poll::Pending => break,
+        }
+    }
+    false
+}
+
+impl IdChannel {
+    fn poll_pending(&mut self, cx: &mut FContext) {
+        let mut in_channels = self.in_channels.lock().unwrap();
+        let mut failed = vec![];
+        for (id, queue) in self.pending.iter_mut() {
+            let channel = match in_channels.get_mut(id) {
+                Some(channel) => channel,
+                None => {
+                    failed.push(*id);
+                    continue;
+                }
+            };
+            if deliver(channel, queue, &mut self.pending_count, cx) {
+                failed.push(*id);
+            }
+        }
+        for id in failed {
+            in_channels.remove(&id);
+            if let Some(queue) = self.pending.remove(&id) {
+                self.pending_count -= queue.len();
+            }
+        }
+        self.pending.retain(|_, queue| !queue.is_empty());
+        let mut index = 0;
+        while index < self.closing.len() {
+            let (channel, queue) = &mut self.closing[index];
+            let done = deliver(channel, queue, &mut self.pending_count, cx)
+                || (queue.is_empty() && channel.as_mut().poll_close(cx).is_ready());
+            if done {
+                let (_, queue) = self.closing.swap_remove(index);
+                self.pending_count -= queue.len();
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
+
 impl ISink<Item> for IdChannel {
     type Error = IdChannelError;
 
-    fn start_send(self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
-        match self.in_channels.lock().unwrap().get_mut(&item.0) {
-            Some(channel) => {
-                let (id, data) = (item.0, item.1);
-                channel
-                    .as_mut()
-                    .start_send(data)
-                    .map_err(move |e| IdChannelError::Channel(SinkStage::Send, id, e))
+    fn start_send(mut self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        if item.0 == CONTROL {
+            if let Ok(control) = item.1.downcast::<Control>() {
+                match *control {
+                    Control::Release(handle) => {
+                        // The peer will send nothing further, so the fork's input ends once
+                        // everything already received has been delivered to it. It is taken
+                        // out of routing at once so that a reuse of the handle never reaches it.
+                        let this = &mut *self;
+                        let queue = this.pending.remove(&handle).unwrap_or_default();
+                        match this.in_channels.lock().unwrap().remove(&handle) {
+                            Some(channel) => this.closing.push((channel, queue)),
+                            None => this.pending_count -= queue.len(),
+                        }
+                        this.context.release(handle);
+                    }
+                }
             }
-            None => Err(IdChannelError::InvalidId(item.0)),
+            return Ok(());
+        }
+        if !self.in_channels.lock().unwrap().contains_key(&item.0) {
+            // Items addressed to a fork that has been torn down are discarded, only
+            // an entirely unknown fork indicates a fault in the connection itself.
+            return match self.context.get(item.0) {
+                Some(_) => Ok(()),
+                None => Err(IdChannelError::InvalidId(item.0)),
+            };
         }
+        let limit = self.context.limits().fork;
+        let this = &mut *self;
+        let queue = this.pending.entry(item.0).or_default();
+        if queue.len() >= limit {
+            // A fork that falls this far behind is closed, rather than holding back every other
+            // fork on the connection until it catches up.
+            let queue = this.pending.remove(&item.0).unwrap_or_default();
+            this.pending_count -= queue.len();
+            this.in_channels.lock().unwrap().remove(&item.0);
+            return Ok(());
+        }
+        queue.push_back(item.1);
+        this.pending_count += 1;
+        Ok(())
     }
-    fn poll_ready(self: Pin<&mut Self>, cx: &mut FContext) -> Poll<Result<(), Self::Error>> {
-        if let Some(result) = self
-            .in_channels
-            .lock()
-            .unwrap()
-            .iter_mut()
-            .map(|(k, item)| (k, item.as_mut().poll_ready(cx)))
-            .find(|(_, poll)| match poll {
-                Poll::Ready(Ok(())) => false,
-                _ => true,
-            })
-        {
-            let (id, result) = result;
-            result.map_err(move |e| IdChannelError::Channel(SinkStage::Ready, *id, e))
-        } else {
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut FContext) -> Poll<Result<(), Self::Error>> {
+        self.poll_pending(cx);
+        if self.pending_count < self.context.limits().connection {
             Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
         }
     }
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut FContext) -> Poll<Result<(), Self::Error>> {
-        if let Some(result) = self
-            .in_channels
-            .lock()
-            .unwrap()
-            .iter_mut()
-            .map(|(k, item)| (k, item.as_mut().poll_ready(cx)))
-            .find(|(_, poll)| match poll {
-                Poll::Ready(Ok(())) => false,
-                _ => true,
-            })
-        {
-            let (id, result) = result;
-            result.map_err(move |e| IdChannelError::Channel(SinkStage::Flush, *id, e))
-        } else {
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut FContext) -> Poll<Result<(), Self::Error>> {
+        self.poll_pending(cx);
+        let mut flushed = self.pending_count == 0;
+        self.in_channels.lock().unwrap().retain(|_, channel| {
+            match channel.as_mut().poll_flush(cx) {
+                Poll::Ready(Ok(())) => true,
+                Poll::Ready(Err(_)) => false,
+                Poll::Pending => {
+                    flushed = false;
+                    true
+                }
+            }
+        });
+        if flushed {
             Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
         }
     }
-    fn poll_close(self: Pin<&mut Self>, cx: &mut FContext) -> Poll<Result<(), Self::Error>> {
-        if let Some(result) = self
-            .in_channels
-            .lock()
-            .unwrap()
-            .iter_mut()
-            .map(|(k, item)| (k, item.as_mut().poll_ready(cx)))
-            .find(|(_, poll)| match poll {
-                Poll::Ready(Ok(())) => false,
-                _ => true,
-            })
-        {
-            let (id, result) = result;
-            result.map_err(move |e| IdChannelError::Channel(SinkStage::Close, *id, e))
-        } else {
-            Poll::Ready(Ok(()))
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut FContext) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        for channel in self.in_channels.lock().unwrap().values_mut() {
+            let _ = channel.as_mut().poll_close(cx);
         }
+        Poll::Ready(Ok(()))
     }
 }
 
+fn in_channel<T: Serialize + Sync + Send + 'static>(
+    sender: Sender<T>,
+    handle: ForkHandle,
+) -> Sink<Box<dyn SerdeAny>, ChannelError> {
+    Box::pin(
+        sender
+            .sink_map_err(|e: SendError| ChannelError(e.into()))
+            .with(move |item: Box<dyn SerdeAny>| {
+                ready(
+                    item.downcast::<T>()
+                        .map(|item| *item)
+                        .map_err(|_| ChannelError(IdChannelError::UnexpectedType(handle).into())),
+                )
+            }),
+    )
+}
+
 impl Waiter for Context {
-    fn wait_for(&self, data: String) -> Future<()> {
-        Box::pin(self.wait_for(ForkHandle(data.parse().unwrap())))
+    fn wait_for(&self, handle: ForkHandle) -> Future<()> {
+        Box::pin(self.wait_for(handle))
+    }
+    fn fail(&self, cause: Error) {
+        self.fail(cause)
     }
 }
 
@@ -182,6 +322,10 @@ impl<'de> IContext<'de> for IdChannel {
     fn context(&self) -> Self::Target {
         self.context.clone()
     }
+
+    fn limits(&self) -> BufferLimits {
+        self.context.limits()
+    }
 }
 
 impl<'de> IContext<'de> for IdChannelHandle {
@@ -191,6 +335,10 @@ impl<'de> IContext<'de> for IdChannelHandle {
     fn context(&self) -> Self::Target {
         self.context.clone()
     }
+
+    fn limits(&self) -> BufferLimits {
+        self.context.limits()
+    }
 }
 
 pub struct Shim<K: Kind> {
@@ -199,26 +347,55 @@ pub struct Shim<K: Kind> {
 }
 
 impl<'a, K: Kind> IShim<'a, IdChannel, K> for Shim<K> {
-    fn complete<C: Sync + Send + Stream<Item = Item> + ISink<Item> + 'static>(
+    fn complete<
+        E: ErrorBound,
+        C: Sync + Send + Stream<Item = Item> + ISink<Item, Error = E> + 'static,
+    >(
         self,
         input: C,
     ) -> Fallible<K, K::ConstructError> {
         let (sink, stream) = input.split();
-        let (sender, receiver) = unbounded();
         let channel = IdChannel {
-            out_channel: (Box::pin(receiver), Box::pin(sender)),
+            outgoing: Outgoing::default(),
             context: self.context,
             in_channels: Arc::new(Mutex::new(HashMap::new())),
+            pending: HashMap::new(),
+            pending_count: 0,
+            closing: vec![],
         };
-        let fork = channel.get_fork::<K>(ForkHandle(0));
+        let fork = channel.get_fork::<K>(ForkHandle(0), Priority::default());
+        let context = channel.context.clone();
         let (sender, receiver) = channel.split();
-        spawn(receiver.map(Ok).forward(sink).unwrap_or_else(|_| panic!()));
-        spawn(
-            stream
-                .map(Ok)
-                .forward(sender)
-                .unwrap_or_else(|e| panic!(format!("{}", e))),
-        );
+        let (failed, failure) = oneshot::channel();
+        {
+            let context = context.clone();
+            spawn(async move {
+                if let Err(e) = receiver.map(Ok).forward(sink).await {
+                    context.fail(e.into());
+                    let _ = failed.send(());
+                }
+            });
+        }
+        spawn(async move {
+            let mut sender = sender;
+            // Should the connection fail to carry outgoing items, incoming ones are no longer
+            // awaited either, so that every fork on it is closed.
+            let incoming = Box::pin(stream.map(Ok).forward(&mut sender));
+            let finished = match select(incoming, failure).await {
+                Either::Left((result, _)) => result.map(|_| true),
+                Either::Right((Ok(()), _)) => Ok(false),
+                Either::Right((Err(_), incoming)) => incoming.await.map(|_| true),
+            };
+            // An item the channel could not route ends the connection as surely as one that
+            // failed to decode, and is reported to its forks in the same way.
+            let finished = finished.unwrap_or_else(|e| {
+                context.fail(e.into());
+                false
+            });
+            if !finished {
+                let _ = sender.close().await;
+            }
+        });
         Box::pin(fork)
     }
 }
@@ -230,72 +407,64 @@ impl<'a, K: Kind> IContext<'a> for Shim<K> {
     fn context(&self) -> Self::Target {
         self.context.clone()
     }
+
+    fn limits(&self) -> BufferLimits {
+        self.context.limits()
+    }
 }
 
 impl IdChannelHandle {
-    fn fork<K: Kind>(&self, kind: K) -> Fallible<ForkHandle, K::DeconstructError> {
+    fn fork<K: Kind>(
+        &self,
+        kind: K,
+        priority: Priority,
+    ) -> Fallible<ForkHandle, K::DeconstructError> {
         REGISTRY.add_deconstruct::<K>();
         let id = self.context.create::<K>();
-        let context = self.context.clone();
-        let out_channel = self.out_channel.clone();
-        let in_channels = self.in_channels.clone();
-
-        Box::pin(
-            IdChannelFork::new(kind, self.clone(), id).map(move |(sender, receiver)| {
-                spawn(
-                    receiver
-                        .map(move |v| Ok(Item::new(id, Box::new(v), context.clone())))
-                        .forward(out_channel)
-                        .unwrap_or_else(|_| panic!()),
-                );
-                let mut in_channels = in_channels.lock().unwrap();
-                in_channels.insert(
-                    id,
-                    Box::pin(
-                        sender
-                            .with(|item: Box<dyn SerdeAny>| {
-                                ok(*(item
-                                    .downcast::<K::DeconstructItem>()
-                                    .map_err(|e| panic!(e))
-                                    .unwrap()))
-                            })
-                            .sink_map_err(|e: SendError| ChannelError(e.into())),
-                    ),
-                );
-                Ok(id)
-            }),
-        )
+        let limit = self.context.limits().fork;
+        let (sender, oo): (Sender<K::DeconstructItem>, _) = channel(limit);
+        let (oi, receiver): (Sender<K::ConstructItem>, _) = channel(limit);
+        self.in_channels
+            .lock()
+            .unwrap()
+            .insert(id, in_channel(sender, id));
+        self.outgoing
+            .add(receiver, id, priority, self.context.clone());
+        spawn(
+            kind.deconstruct(IdChannelFork {
+                o: Box::pin(oi),
+                i: Box::pin(oo),
+                handle: id,
+                priority,
+                channel: self.clone(),
+                sink_item: PhantomData,
+            })
+            .unwrap_or_else(|_| ()),
+        );
+        Box::pin(ok(id))
     }
 
-    fn get_fork<K: Kind>(&self, fork_ref: ForkHandle) -> Fallible<K, K::ConstructError> {
-        let out_channel = self.out_channel.clone();
+    fn get_fork<K: Kind>(
+        &self,
+        fork_ref: ForkHandle,
+        priority: Priority,
+    ) -> Fallible<K, K::ConstructError> {
         REGISTRY.add_construct::<K>();
+        let limit = self.context.limits().fork;
+        let (sender, ireceiver): (Sender<K::DeconstructItem>, _) = channel(limit);
+        let (isender, receiver): (Sender<K::ConstructItem>, _) = channel(limit);
+        self.in_channels
+            .lock()
+            .unwrap()
+            .insert(fork_ref, in_channel(isender, fork_ref));
         self.context.add::<K>(fork_ref);
-        let (sender, ireceiver): (UnboundedSender<K::DeconstructItem>, _) = unbounded();
-        let (isender, receiver): (UnboundedSender<K::ConstructItem>, _) = unbounded();
-        let isender = isender.with(|item: Box<dyn SerdeAny>| {
-            ok(*(match item.downcast::<K::ConstructItem>() {
-                Ok(item) => item,
-                Err(_) => panic!(),
-            }))
-        });
-        self.in_channels.lock().unwrap().insert(
-            fork_ref,
-            Box::pin(isender.sink_map_err(|e: SendError| ChannelError(e.into()))),
-        );
-        let ct = self.context.clone();
-        spawn(
-            ireceiver
-                .map(move |item: K::DeconstructItem| {
-                    Ok(Item::new(fork_ref, Box::new(item), ct.clone()))
-                })
-                .forward(out_channel)
-                .unwrap_or_else(|_| panic!()),
-        );
+        self.outgoing
+            .add(ireceiver, fork_ref, priority, self.context.clone());
         Box::pin(K::construct(IdChannelFork {
             o: Box::pin(sender),
             i: Box::pin(receiver),
             handle: fork_ref,
+            priority,
             channel: self.clone(),
             sink_item: PhantomData,
         }))
@@ -303,31 +472,40 @@ impl IdChannelHandle {
 }
 
 impl IdChannel {
+    /// Returns a `Forks` for inspecting the forks of this channel.
+    pub fn forks(&self) -> Forks {
+        Forks(self.context.clone())
+    }
+
     fn clone(&self) -> IdChannelHandle {
         IdChannelHandle {
-            out_channel: self.out_channel.1.clone(),
+            outgoing: self.outgoing.clone(),
             context: self.context.clone(),
             in_channels: self.in_channels.clone(),
         }
     }
-    fn get_fork<K: Kind>(&self, fork_ref: ForkHandle) -> Fallible<K, K::ConstructError> {
-        self.clone().get_fork(fork_ref)
+    fn get_fork<K: Kind>(
+        &self,
+        fork_ref: ForkHandle,
+        priority: Priority,
+    ) -> Fallible<K, K::ConstructError> {
+        self.clone().get_fork(fork_ref, priority)
     }
 }
 
 impl<'a, K: Kind> Target<'a, K> for IdChannel {
     type Shim = Shim<K>;
 
-    fn new_with(kind: K) -> Future<Self>
+    fn new_with_limits(kind: K, limits: BufferLimits) -> Future<Self>
     where
         K::DeconstructFuture: Send,
     {
-        Box::pin(IdChannelFork::new_root(kind))
+        Box::pin(IdChannelFork::new_root(kind, limits))
     }
 
-    fn new_shim() -> Self::Shim {
+    fn new_shim_with_limits(limits: BufferLimits) -> Self::Shim {
         REGISTRY.add_construct::<K>();
-        let context = Context::new_shim();
+        let context = Context::new_shim(limits);
         context.add::<K>(ForkHandle(0));
         Shim {
             context,
@@ -342,10 +520,27 @@ impl<
     > IFork for IdChannelFork<I, O>
 {
     fn fork<K: Kind>(&self, kind: K) -> Fallible<ForkHandle, K::DeconstructError> {
-        self.channel.fork(kind)
+        self.channel.fork(kind, self.priority)
     }
     fn get_fork<K: Kind>(&self, fork_ref: ForkHandle) -> Fallible<K, K::ConstructError> {
-        self.channel.get_fork(fork_ref)
+        self.channel.get_fork(fork_ref, self.priority)
+    }
+    fn fork_with_priority<K: Kind>(
+        &self,
+        kind: K,
+        priority: Priority,
+    ) -> Fallible<ForkHandle, K::DeconstructError> {
+        self.channel.fork(kind, priority)
+    }
+    fn get_fork_with_priority<K: Kind>(
+        &self,
+        fork_ref: ForkHandle,
+        priority: Priority,
+    ) -> Fallible<K, K::ConstructError> {
+        self.channel.get_fork(fork_ref, priority)
+    }
+    fn lost(&self) -> TransportError {
+        self.channel.context.lost()
     }
 }
 
@@ -353,10 +548,11 @@ pub(crate) struct IdChannelFork<
     I: Serialize + DeserializeOwned + Sync + Send + 'static,
     O: Serialize + DeserializeOwned + Sync + Send + Unpin + 'static,
 > {
-    i: Pin<Box<UnboundedReceiver<I>>>,
-    o: Pin<Box<UnboundedSender<O>>>,
+    i: Pin<Box<Receiver<I>>>,
+    o: Pin<Box<Sender<O>>>,
     channel: IdChannelHandle,
     handle: ForkHandle,
+    priority: Priority,
     sink_item: PhantomData<O>,
 }
 
@@ -387,79 +583,43 @@ impl<
         O: Serialize + DeserializeOwned + Sync + Send + Unpin + 'static,
     > IdChannelFork<I, O>
 {
-    fn new<K: Kind<DeconstructItem = I, ConstructItem = O>>(
-        kind: K,
-        channel: IdChannelHandle,
-        handle: ForkHandle,
-    ) -> impl IFuture<Output = (UnboundedSender<I>, UnboundedReceiver<O>)>
-    where
-        K::DeconstructFuture: Sync + Send + 'static,
-    {
-        async move {
-            let (sender, oo): (UnboundedSender<I>, UnboundedReceiver<I>) = unbounded();
-            let (oi, receiver): (UnboundedSender<O>, UnboundedReceiver<O>) = unbounded();
-            spawn(
-                kind.deconstruct(IdChannelFork {
-                    o: Box::pin(oi),
-                    i: Box::pin(oo),
-                    handle,
-                    channel,
-                    sink_item: PhantomData,
-                })
-                .unwrap_or_else(|_| panic!()),
-            );
-            (sender, receiver)
-        }
-    }
-
     fn new_root<K: Kind<DeconstructItem = I, ConstructItem = O>>(
         kind: K,
+        limits: BufferLimits,
     ) -> impl IFuture<Output = IdChannel>
     where
         K::DeconstructFuture: Sync + Send + 'static,
     {
         async move {
-            let (sender, oo): (UnboundedSender<I>, UnboundedReceiver<I>) = unbounded();
-            let (oi, receiver): (UnboundedSender<O>, UnboundedReceiver<O>) = unbounded();
+            let (sender, oo): (Sender<I>, Receiver<I>) = channel(limits.fork);
+            let (oi, receiver): (Sender<O>, Receiver<O>) = channel(limits.fork);
             let mut in_channels = HashMap::new();
             REGISTRY.add_deconstruct::<K>();
-            let context = Context::new();
+            let context = Context::new(limits);
             let handle = context.create::<K>();
-            in_channels.insert(
-                handle,
-                Box::pin(
-                    sender
-                        .with(|item: Box<dyn SerdeAny>| {
-                            ok(*(item
-                                .downcast::<K::DeconstructItem>()
-                                .map_err(|_| panic!())
-                                .unwrap()))
-                        })
-                        .sink_map_err(|e: SendError| ChannelError(e.into())),
-                ) as Sink<Box<dyn SerdeAny>, ChannelError>,
-            );
-            let ct = context.clone();
-            let (csender, creceiver) = unbounded();
+            in_channels.insert(handle, in_channel(sender, handle));
+            let priority = Priority::default();
             let channel = IdChannel {
-                out_channel: (Box::pin(creceiver), Box::pin(csender.clone())),
+                outgoing: Outgoing::default(),
                 context,
                 in_channels: Arc::new(Mutex::new(in_channels)),
+                pending: HashMap::new(),
+                pending_count: 0,
+                closing: vec![],
             };
-            spawn(
-                receiver
-                    .map(move |v| Ok(Item::new(handle, Box::new(v), ct.clone())))
-                    .forward(csender)
-                    .unwrap_or_else(|_| panic!()),
-            );
+            channel
+                .outgoing
+                .add(receiver, handle, priority, channel.context.clone());
             spawn(
                 kind.deconstruct(IdChannelFork {
                     o: Box::pin(oi),
                     i: Box::pin(oo),
                     handle,
+                    priority,
                     channel: channel.clone(),
                     sink_item: PhantomData,
                 })
-                .unwrap_or_else(|_| panic!()),
+                .unwrap_or_else(|_| ()),
             );
             channel
         }
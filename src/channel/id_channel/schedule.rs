@@ -0,0 +1,183 @@
+use super::{Context, Control, Item, CONTROL};
+
+use crate::{
+    channel::{ForkHandle, Priority},
+    kind::Stream,
+};
+
+use alloc::sync::{Arc, Weak};
+use core::pin::Pin;
+use futures::{
+    channel::mpsc::Receiver,
+    future::ready,
+    stream::once,
+    task::{waker, ArcWake, Context as FContext, Poll, Waker},
+    Stream as IStream, StreamExt,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Mutex,
+};
+
+struct Source {
+    /// Taken while the source is being polled, so that it is never polled under the lock.
+    items: Option<Stream<Item>>,
+    priority: Priority,
+    waker: Waker,
+    /// Whether the source is in the ready queue of its priority.
+    queued: bool,
+}
+
+#[derive(Default)]
+struct Scheduler {
+    next: u64,
+    sources: HashMap<u64, Source>,
+    /// The sources that may have an item, by priority. A priority with none is removed.
+    ready: BTreeMap<Priority, VecDeque<u64>>,
+    waker: Option<Waker>,
+}
+
+impl Scheduler {
+    /// Queues the source `id` to be polled, returning the waker of the `Outgoing` if it must be
+    /// woken, which is to be done once the lock is released.
+    fn queue(&mut self, id: u64) -> Option<Waker> {
+        let source = self.sources.get_mut(&id)?;
+        if !source.queued {
+            source.queued = true;
+            self.ready.entry(source.priority).or_default().push_back(id);
+        }
+        self.waker.take()
+    }
+
+    /// Takes the next source to be polled, from those of the highest priority that are ready.
+    fn next(&mut self) -> Option<u64> {
+        let (&priority, queue) = self.ready.iter_mut().next_back()?;
+        let id = queue.pop_front();
+        if queue.is_empty() {
+            self.ready.remove(&priority);
+        }
+        if let Some(source) = id.and_then(|id| self.sources.get_mut(&id)) {
+            source.queued = false;
+        }
+        id
+    }
+}
+
+/// Wakes an `Outgoing` on behalf of one of its sources, queuing that source alone to be polled.
+struct SourceWaker {
+    id: u64,
+    scheduler: Weak<Mutex<Scheduler>>,
+}
+
+impl ArcWake for SourceWaker {
+    fn wake_by_ref(this: &Arc<Self>) {
+        if let Some(scheduler) = this.scheduler.upgrade() {
+            let waker = scheduler.lock().unwrap().queue(this.id);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Schedules the outgoing items of every fork of a connection, taking items from forks of a
+/// higher priority first and from those of the same priority in turn. Only forks that have
+/// been woken since they were last polled are polled again.
+#[derive(Clone, Default)]
+pub(crate) struct Outgoing(Arc<Mutex<Scheduler>>);
+
+impl Outgoing {
+    /// Adds the items sent on the fork `handle` to those to be scheduled, followed by the release
+    /// of the fork once there are no more.
+    pub(crate) fn add<T: Serialize + Sync + Send + 'static>(
+        &self,
+        items: Receiver<T>,
+        handle: ForkHandle,
+        priority: Priority,
+        context: Context,
+    ) {
+        let ct = context.clone();
+        let released = context.clone();
+        let items = items
+            .map(move |item| Item::new(handle, Box::new(item), ct.clone()))
+            .chain(once(async move {
+                // The peer must see the release before a handle allocated here can be reused,
+                // whereas a handle allocated by the peer must be forgotten here before the peer
+                // can reuse it.
+                if !context.is_local(handle) {
+                    context.release(handle);
+                }
+                Item::new(CONTROL, Box::new(Control::Release(handle)), context)
+            }))
+            .chain(
+                once(async move {
+                    if released.is_local(handle) {
+                        released.release(handle);
+                    }
+                })
+                .filter_map(|_| ready(None)),
+            );
+        let mut scheduler = self.0.lock().unwrap();
+        let id = scheduler.next;
+        scheduler.next += 1;
+        scheduler.sources.insert(
+            id,
+            Source {
+                items: Some(Box::pin(items)),
+                priority,
+                waker: waker(Arc::new(SourceWaker {
+                    id,
+                    scheduler: Arc::downgrade(&self.0),
+                })),
+                queued: false,
+            },
+        );
+        let waker = scheduler.queue(id);
+        drop(scheduler);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl IStream for Outgoing {
+    type Item = Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut FContext) -> Poll<Option<Self::Item>> {
+        loop {
+            let (id, mut items, waker) = {
+                let mut scheduler = self.0.lock().unwrap();
+                let id = match scheduler.next() {
+                    Some(id) => id,
+                    None => {
+                        scheduler.waker = Some(cx.waker().clone());
+                        return Poll::Pending;
+                    }
+                };
+                match scheduler.sources.get_mut(&id) {
+                    Some(source) => match source.items.take() {
+                        Some(items) => (id, items, source.waker.clone()),
+                        None => continue,
+                    },
+                    None => continue,
+                }
+            };
+            let polled = items.as_mut().poll_next(&mut FContext::from_waker(&waker));
+            let mut scheduler = self.0.lock().unwrap();
+            match polled {
+                Poll::Ready(Some(item)) => {
+                    // The source may have further items without waking, so it takes its turn
+                    // again after the other ready sources of its priority.
+                    scheduler.sources.get_mut(&id).unwrap().items = Some(items);
+                    scheduler.queue(id);
+                    return Poll::Ready(Some(item));
+                }
+                Poll::Ready(None) => {
+                    scheduler.sources.remove(&id);
+                }
+                Poll::Pending => scheduler.sources.get_mut(&id).unwrap().items = Some(items),
+            }
+        }
+    }
+}
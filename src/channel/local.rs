@@ -0,0 +1,255 @@
+//! A channel for transferring `Kind`s within a single process.
+//!
+//! Where both sides of a `Kind` are in the same process there is nothing to encode, so rather
+//! than going through a `Target` such as `IdChannel` and a `Format`, `Local` passes the typed
+//! items of each fork directly between `deconstruct` and `construct`. Forks are established by
+//! handing the receiving ends of a fork's typed channels to `get_fork` under its handle, with no
+//! serialization and no item registry involved.
+
+use crate::{
+    channel::{BufferLimits, Channel, ChannelError, Fork, ForkHandle},
+    core::spawn,
+    kind::{Fallible, TransportError},
+    Kind,
+};
+
+use alloc::sync::Arc;
+use anyhow::{anyhow, Error};
+use core::{
+    any::{type_name, Any},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use futures::{
+    channel::mpsc::{channel, Receiver, Sender},
+    future::ok,
+    Sink, Stream, TryFutureExt,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+/// The ends of forks that have been created but not yet constructed.
+#[derive(Default)]
+struct Ends {
+    next: u32,
+    ends: HashMap<ForkHandle, Unclaimed>,
+}
+
+/// The forks created for one side of a fork to construct that it has not yet constructed.
+type Claims = Arc<Mutex<HashSet<ForkHandle>>>;
+
+/// The typed end of a fork awaiting construction.
+type End<K> = (
+    Receiver<<K as Kind>::ConstructItem>,
+    Sender<<K as Kind>::DeconstructItem>,
+);
+
+/// A fork awaiting construction, along with the claims of either side of it.
+struct Unclaimed {
+    end: Box<dyn Any + Sync + Send>,
+    claims: Claims,
+    peer: Claims,
+}
+
+#[derive(Clone)]
+struct LocalHandle {
+    ends: Arc<Mutex<Ends>>,
+    limits: BufferLimits,
+}
+
+impl LocalHandle {
+    /// Creates a fork deconstructing `kind`, to be constructed by the side holding `claims`.
+    fn fork<K: Kind>(&self, kind: K, claims: &Claims) -> ForkHandle {
+        let (construct_sender, construct_receiver) = channel::<K::ConstructItem>(self.limits.fork);
+        let (deconstruct_sender, deconstruct_receiver) =
+            channel::<K::DeconstructItem>(self.limits.fork);
+        let unclaimed = Unclaimed {
+            end: Box::new((construct_receiver, deconstruct_sender) as End<K>),
+            claims: Claims::default(),
+            peer: Claims::default(),
+        };
+        let fork = LocalFork {
+            i: deconstruct_receiver,
+            o: construct_sender,
+            channel: self.clone(),
+            claims: unclaimed.peer.clone(),
+            peer: unclaimed.claims.clone(),
+            failure: None,
+        };
+        let handle = {
+            let mut ends = self.ends.lock().unwrap();
+            let handle = ForkHandle(ends.next);
+            ends.next += 1;
+            ends.ends.insert(handle, unclaimed);
+            handle
+        };
+        claims.lock().unwrap().insert(handle);
+        spawn(kind.deconstruct(fork).unwrap_or_else(|_| ()));
+        handle
+    }
+
+    /// Constructs the fork `handle` on behalf of the side holding `claims`.
+    fn get_fork<K: Kind>(
+        &self,
+        handle: ForkHandle,
+        claims: &Claims,
+    ) -> Fallible<K, K::ConstructError> {
+        claims.lock().unwrap().remove(&handle);
+        let unclaimed = self.ends.lock().unwrap().ends.remove(&handle);
+        // A fork that does not exist or is of another type is constructed from a closed
+        // channel, as it would be from a connection that carries nothing for it, with the
+        // reason reported as the cause of its loss.
+        let failure = match unclaimed {
+            Some(Unclaimed { end, claims, peer }) => match end.downcast::<End<K>>() {
+                Ok(end) => {
+                    let (i, o) = *end;
+                    return Box::pin(K::construct(LocalFork {
+                        i,
+                        o,
+                        channel: self.clone(),
+                        claims,
+                        peer,
+                        failure: None,
+                    }));
+                }
+                Err(_) => {
+                    self.abandon(claims.lock().unwrap().drain().collect());
+                    anyhow!("fork {} is not of type {}", handle, type_name::<K>())
+                }
+            },
+            None => anyhow!("fork {} does not exist", handle),
+        };
+        Box::pin(K::construct(LocalFork {
+            i: channel(0).1,
+            o: channel(0).0,
+            channel: self.clone(),
+            claims: Claims::default(),
+            peer: Claims::default(),
+            failure: Some(Arc::new(failure)),
+        }))
+    }
+
+    /// Drops the forks `handles`, which will never be constructed, along with any created for
+    /// them to construct in turn, so that their deconstruction ends.
+    fn abandon(&self, mut handles: Vec<ForkHandle>) {
+        let mut abandoned = vec![];
+        {
+            let mut ends = self.ends.lock().unwrap();
+            while let Some(handle) = handles.pop() {
+                if let Some(unclaimed) = ends.ends.remove(&handle) {
+                    handles.extend(unclaimed.claims.lock().unwrap().drain());
+                    abandoned.push(unclaimed);
+                }
+            }
+        }
+        drop(abandoned);
+    }
+}
+
+/// Transfers `Kind`s within a single process without serializing them.
+pub struct Local;
+
+impl Local {
+    /// Deconstructs `kind` and constructs it again on the other side of a local channel.
+    pub fn transfer<K: Kind>(kind: K) -> Fallible<K, K::ConstructError> {
+        Local::transfer_with_limits(kind, BufferLimits::default())
+    }
+
+    /// As `transfer`, bounding the items queued on each fork by `limits.fork`. The other limits
+    /// concern connections and do not apply.
+    pub fn transfer_with_limits<K: Kind>(
+        kind: K,
+        limits: BufferLimits,
+    ) -> Fallible<K, K::ConstructError> {
+        let channel = LocalHandle {
+            ends: Arc::new(Mutex::new(Ends::default())),
+            limits,
+        };
+        let claims = Claims::default();
+        let handle = channel.fork(kind, &claims);
+        channel.get_fork(handle, &claims)
+    }
+}
+
+/// One side of a fork of a local channel.
+pub struct LocalFork<I, O> {
+    i: Receiver<I>,
+    o: Sender<O>,
+    channel: LocalHandle,
+    claims: Claims,
+    peer: Claims,
+    failure: Option<Arc<Error>>,
+}
+
+impl<I, O> Drop for LocalFork<I, O> {
+    fn drop(&mut self) {
+        // Closing first means that any fork created for this side after its claims are taken
+        // sees it closed and is abandoned by its creator instead.
+        self.i.close();
+        let handles = self.claims.lock().unwrap().drain().collect();
+        self.channel.abandon(handles);
+    }
+}
+
+impl<I, O> Stream for LocalFork<I, O> {
+    type Item = I;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.i).poll_next(cx)
+    }
+}
+
+impl<I, O> Sink<O> for LocalFork<I, O> {
+    type Error = ChannelError;
+
+    fn start_send(mut self: Pin<&mut Self>, item: O) -> Result<(), Self::Error> {
+        Pin::new(&mut self.o)
+            .start_send(item)
+            .map_err(|e| ChannelError(e.into()))
+    }
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.o)
+            .poll_ready(cx)
+            .map_err(|e| ChannelError(e.into()))
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.o)
+            .poll_flush(cx)
+            .map_err(|e| ChannelError(e.into()))
+    }
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.o)
+            .poll_close(cx)
+            .map_err(|e| ChannelError(e.into()))
+    }
+}
+
+impl<I: Sync + Send + 'static, O: Sync + Send + 'static> Fork for LocalFork<I, O> {
+    fn fork<K: Kind>(&self, kind: K) -> Fallible<ForkHandle, K::DeconstructError> {
+        let handle = self.channel.fork(kind, &self.peer);
+        // The other side, having been dropped, will never construct the fork.
+        if self.o.is_closed() && self.peer.lock().unwrap().remove(&handle) {
+            self.channel.abandon(vec![handle]);
+        }
+        Box::pin(ok(handle))
+    }
+    fn get_fork<K: Kind>(&self, handle: ForkHandle) -> Fallible<K, K::ConstructError> {
+        self.channel.get_fork(handle, &self.claims)
+    }
+    fn lost(&self) -> TransportError {
+        match &self.failure {
+            Some(cause) => TransportError::failed(cause.clone()),
+            None => TransportError::lost(),
+        }
+    }
+}
+
+impl<
+        I: Serialize + DeserializeOwned + Sync + Send + 'static,
+        O: Serialize + DeserializeOwned + Sync + Send + 'static,
+    > Channel<I, O> for LocalFork<I, O>
+{
+}
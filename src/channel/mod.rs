@@ -1,13 +1,18 @@
 pub mod id_channel;
 pub use id_channel::IdChannel;
+pub mod local;
+pub use local::Local;
 
 use crate::{
-    kind::{Fallible, Future},
-    Kind,
+    kind::{Fallible, Future, TransportError},
+    ErrorBound, Kind,
 };
 
 use anyhow::Error;
-use core::fmt::{self, Display, Formatter};
+use core::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
 use futures::{Sink, Stream};
 use serde::{
     de::{DeserializeOwned, DeserializeSeed},
@@ -25,9 +30,74 @@ impl Display for ForkHandle {
     }
 }
 
+/// Limits on the number of items a channel will buffer before exerting backpressure.
+///
+/// `fork` bounds the items queued on any single fork in either direction and `connection`
+/// bounds the items queued on the shared connection that carries all forks, including the
+/// number of frames a `Format` will decode concurrently. A fork that falls behind by more than
+/// twice `fork` items received for it is closed, so that it never holds back the other forks
+/// on its connection.
+///
+/// Frames that arrive for a fork which has not yet been established are held until it is,
+/// at most `pending` of them per fork and for no longer than `pending_timeout`. Vessels have no
+/// timer, so within them `pending_timeout` is not enforced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferLimits {
+    pub fork: usize,
+    pub connection: usize,
+    pub pending: usize,
+    pub pending_timeout: Duration,
+}
+
+impl Default for BufferLimits {
+    fn default() -> Self {
+        BufferLimits {
+            fork: 64,
+            connection: 256,
+            pending: 64,
+            pending_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The priority with which the outgoing items of a fork share a connection.
+///
+/// Items of a higher priority are always sent before those of a lower one, while forks of the
+/// same priority take turns sending an item each. Forks take the priority of the fork they were
+/// created on unless one is given explicitly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
 pub trait Fork: Sync + Send + 'static {
     fn fork<K: Kind>(&self, kind: K) -> Fallible<ForkHandle, K::DeconstructError>;
     fn get_fork<K: Kind>(&self, fork_ref: ForkHandle) -> Fallible<K, K::ConstructError>;
+    /// As `fork`, sending the items of the new fork with `priority`. Channels that do not share
+    /// a connection between forks disregard it.
+    fn fork_with_priority<K: Kind>(
+        &self,
+        kind: K,
+        _priority: Priority,
+    ) -> Fallible<ForkHandle, K::DeconstructError> {
+        self.fork(kind)
+    }
+    /// As `get_fork`, sending the items of the fork with `priority`.
+    fn get_fork_with_priority<K: Kind>(
+        &self,
+        fork_ref: ForkHandle,
+        _priority: Priority,
+    ) -> Fallible<K, K::ConstructError> {
+        self.get_fork(fork_ref)
+    }
+    /// The error with which items that will now never arrive on this channel are reported,
+    /// carrying the failure that ended the connection where it is known.
+    fn lost(&self) -> TransportError {
+        TransportError::lost()
+    }
 }
 
 #[derive(Debug, Error)]
@@ -50,10 +120,11 @@ pub trait Shim<'a, T: Target<'a, K>, K: Kind>:
     Context<'a, Item = <T as Context<'a>>::Item>
 {
     fn complete<
+        E: ErrorBound,
         C: Sync
             + Send
             + Stream<Item = <T as Context<'a>>::Item>
-            + Sink<<T as Context<'a>>::Item>
+            + Sink<<T as Context<'a>>::Item, Error = E>
             + 'static,
     >(
         self,
@@ -65,21 +136,52 @@ pub trait Target<'a, K: Kind>: Context<'a> + Sized + Send + Sync {
     type Shim: Shim<'a, Self, K>;
 
     fn new_with(kind: K) -> Future<Self>
+    where
+        K::DeconstructFuture: Send,
+    {
+        Self::new_with_limits(kind, BufferLimits::default())
+    }
+
+    fn new_with_limits(kind: K, limits: BufferLimits) -> Future<Self>
     where
         K::DeconstructFuture: Send;
 
-    fn new_shim() -> Self::Shim;
+    fn new_shim() -> Self::Shim {
+        Self::new_shim_with_limits(BufferLimits::default())
+    }
+
+    fn new_shim_with_limits(limits: BufferLimits) -> Self::Shim;
+}
+
+/// An item deserialized from a connection.
+pub enum Incoming<T> {
+    /// An item addressed to an established fork.
+    Ready(ForkHandle, T),
+    /// An item addressed to a fork that has not yet been established. The frame it was read from
+    /// must be deserialized again once that fork exists.
+    Pending(ForkHandle),
 }
 
 pub trait Waiter {
-    fn wait_for(&self, data: String) -> Future<()>;
+    /// Resolves once the fork `handle` has been established.
+    fn wait_for(&self, handle: ForkHandle) -> Future<()>;
+    /// Records the failure that ended the connection, so that it is reported as the cause of
+    /// the loss of every fork on it.
+    fn fail(&self, _cause: Error) {}
 }
 
 pub trait Context<'de> {
     type Item: Serialize + Sync + Send + 'static;
-    type Target: Waiter + DeserializeSeed<'de, Value = Self::Item> + Clone + Sync + Send + 'static;
+    type Target: Waiter
+        + DeserializeSeed<'de, Value = Incoming<Self::Item>>
+        + Clone
+        + Sync
+        + Send
+        + 'static;
 
     fn context(&self) -> Self::Target;
+
+    fn limits(&self) -> BufferLimits;
 }
 
 pub trait OnTo: Kind {
@@ -87,6 +189,11 @@ pub trait OnTo: Kind {
     where
         Self: Send + 'static,
         Self::DeconstructFuture: Sync + Send;
+
+    fn on_to_with_limits<'a, T: Target<'a, Self>>(self, limits: BufferLimits) -> Future<T>
+    where
+        Self: Send + 'static,
+        Self::DeconstructFuture: Sync + Send;
 }
 
 impl<K: Kind> OnTo for K {
@@ -97,4 +204,12 @@ impl<K: Kind> OnTo for K {
     {
         T::new_with(self)
     }
+
+    fn on_to_with_limits<'a, T: Target<'a, Self>>(self, limits: BufferLimits) -> Future<T>
+    where
+        Self: Send + 'static,
+        Self::DeconstructFuture: Sync + Send,
+    {
+        T::new_with_limits(self, limits)
+    }
 }
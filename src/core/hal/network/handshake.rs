@@ -0,0 +1,100 @@
+use super::ConnectionError;
+
+use crate::{kind::SinkStream, Kind};
+
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The version of the protocol spoken over connections, incremented on incompatible changes.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+#[derive(Error, Debug, Kind)]
+pub enum HandshakeError {
+    #[error("peer speaks protocol version {remote}, expected {local}")]
+    Version { local: u32, remote: u32 },
+    #[error("peer supports none of the formats {local:?}, only {remote:?}")]
+    Format {
+        local: Vec<String>,
+        remote: Vec<String>,
+    },
+    #[error("root Kind of the peer does not match")]
+    Kind,
+    #[error("peer did not send a valid handshake")]
+    Malformed,
+    #[error("connection closed during handshake")]
+    Closed,
+    #[error("{0}")]
+    Connection(#[source] ConnectionError),
+}
+
+/// Sent by each end of a connection before anything else, so that both can confirm they agree
+/// on what is being transported before fork 0 is opened.
+///
+/// Each end offers the names of the formats it supports. The connection uses the first format
+/// offered by the client that the server also supports.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Handshake {
+    version: u32,
+    formats: Vec<String>,
+    kind: [u8; 32],
+    #[serde(skip)]
+    client: bool,
+}
+
+impl Handshake {
+    pub(crate) fn client<K: Kind>(formats: Vec<String>) -> Self {
+        Handshake {
+            version: PROTOCOL_VERSION,
+            formats,
+            kind: K::USE_KIND_MACRO_TO_GENERATE_THIS_FIELD,
+            client: true,
+        }
+    }
+
+    pub(crate) fn server<K: Kind>(formats: Vec<String>) -> Self {
+        Handshake {
+            client: false,
+            ..Handshake::client::<K>(formats)
+        }
+    }
+
+    /// Sends this handshake on `connection` and checks it against the one the peer sends,
+    /// returning the name of the format the connection is to use.
+    pub(crate) async fn exchange(
+        self,
+        connection: &mut SinkStream<Vec<u8>, ConnectionError, Vec<u8>>,
+    ) -> Result<String, HandshakeError> {
+        // The handshake is encoded independently of the `Format` in use, as it is what
+        // establishes which one both ends use.
+        connection
+            .send(serde_cbor::to_vec(&self).unwrap())
+            .await
+            .map_err(HandshakeError::Connection)?;
+        let remote = connection.next().await.ok_or(HandshakeError::Closed)?;
+        let remote: Handshake =
+            serde_cbor::from_slice(&remote).map_err(|_| HandshakeError::Malformed)?;
+        let (client, server) = if self.client {
+            (&self.formats, &remote.formats)
+        } else {
+            (&remote.formats, &self.formats)
+        };
+        let format = client
+            .iter()
+            .find(|format| server.contains(format))
+            .cloned();
+        if remote.version != self.version {
+            Err(HandshakeError::Version {
+                local: self.version,
+                remote: remote.version,
+            })
+        } else if remote.kind != self.kind {
+            Err(HandshakeError::Kind)
+        } else {
+            format.ok_or(HandshakeError::Format {
+                local: self.formats,
+                remote: remote.formats,
+            })
+        }
+    }
+}
@@ -1,17 +1,28 @@
 use crate::{
     channel::{Context, OnTo, Target},
     core::{spawn, UnimplementedError},
-    format::{ApplyDecode, ApplyEncode, Format},
+    format::{erased, ApplyDecode, Binary, Format, Formats, Layer, Plain, UniformStreamSink},
     kind::{Fallible, Future, Infallible, SinkStream, TransportError},
     object, Kind,
 };
 
 use anyhow::Error;
-use futures::{future::ready, lock::Mutex, FutureExt, Sink, StreamExt};
-use std::{net::SocketAddr, sync::Arc};
+use futures::{
+    future::{ok, ready, select, Either},
+    lock::Mutex,
+    FutureExt, Sink, SinkExt, StreamExt,
+};
+use futures_timer::Delay;
+use std::{net::SocketAddr, path::Path, sync::Arc, time::Duration};
 use thiserror::Error;
 use url::Url;
 
+mod handshake;
+use handshake::Handshake;
+pub use handshake::{HandshakeError, PROTOCOL_VERSION};
+mod session;
+pub use session::Resumption;
+
 #[object]
 pub trait Peer {}
 
@@ -19,9 +30,12 @@ pub trait Peer {}
 pub enum ConnectError {
     #[error("connection failed: {0}")]
     Connect(#[source] Error),
+    #[error("handshake failed: {0}")]
+    Handshake(#[source] HandshakeError),
     #[error("construct failed: {0}")]
     Construct(#[source] Error),
     #[error("underlying transport failed: {0}")]
+    #[kind(lost)]
     Transport(#[from] TransportError),
 }
 
@@ -55,6 +69,37 @@ impl From<TransportError> for ConnectionError {
     }
 }
 
+/// Credentials of the process at the other end of a Unix domain socket, as reported by the
+/// operating system when it connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Kind)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// The process id of the peer, which not every platform reports.
+    pub pid: Option<i32>,
+}
+
+/// Keepalive behaviour of connections, used to notice peers that have gone away silently.
+///
+/// A ping is sent every `interval` and the connection is considered lost if nothing at all is
+/// received from the peer for `timeout`, at which point every remote `Future` and `Stream` on it
+/// resolves with a `TransportError`. Only native connections send pings, though pings from the
+/// other end are answered by any connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Keepalive {
+            interval: Duration::from_secs(15),
+            timeout: Duration::from_secs(45),
+        }
+    }
+}
+
 #[object]
 pub(crate) trait RawClient {
     fn connect(
@@ -68,21 +113,81 @@ pub struct Client(Box<dyn RawClient>);
 
 impl Client {
     pub fn new() -> Result<Client, UnimplementedError> {
-        RawClient::new().map(Client)
-    }
-    pub fn connect<
-        'a,
-        K: Kind,
-        T: Target<'a, K> + 'static,
-        F: Format<Representation = Vec<u8>> + 'static,
-    >(
+        Client::new_with_keepalive(Keepalive::default())
+    }
+    pub fn new_with_keepalive(keepalive: Keepalive) -> Result<Client, UnimplementedError> {
+        <dyn RawClient>::new(keepalive).map(Client)
+    }
+    /// Connects to the server at `address`, constructing the `Kind` it serves. The connection
+    /// uses the format `F`, which the server must support. The client offers no other, so
+    /// formats are only negotiated by servers, each serving a client in the one it offers from
+    /// among those advertised with `Server::listen_negotiated`.
+    ///
+    /// The transport is chosen by the scheme of `address`: `ws://` and `wss://` connect over a
+    /// websocket, `tcp://` connects over plain TCP to a server created with `Server::new_tcp`,
+    /// and `unix:///run/app.sock` connects to the Unix domain socket at `/run/app.sock` of a
+    /// server created with `Server::new_unix`. Neither of the latter is available on the web.
+    pub fn connect<'a, K: Kind, T: Target<'a, K> + 'static, F: Format + 'static>(
         &mut self,
         address: Url,
-    ) -> Fallible<K, ConnectError> {
+    ) -> Fallible<K, ConnectError>
+    where
+        F::Representation: Binary + Clone + Sync + Send + 'static,
+    {
+        self.connect_through::<K, T, F>(address, Box::new(Plain))
+    }
+    /// Connects as `connect` does, carrying every frame of the connection through `layer`. The
+    /// server must offer `F` through the same layer, as added with `Formats::with_layer`.
+    pub fn connect_through<'a, K: Kind, T: Target<'a, K> + 'static, F: Format + 'static>(
+        &mut self,
+        address: Url,
+        layer: Box<dyn Layer<F::Representation>>,
+    ) -> Fallible<K, ConnectError>
+    where
+        F::Representation: Binary + Clone + Sync + Send + 'static,
+    {
         let connection = self.0.connect(address);
         Box::pin(async move {
-            connection
-                .await?
+            let mut connection = connection.await?;
+            Handshake::client::<K>(vec![layer.name(F::name())])
+                .exchange(&mut connection)
+                .await
+                .map_err(ConnectError::Handshake)?;
+            represent(connection)
+                .decode_through::<T, F>(layer)
+                .await
+                .map_err(|e| ConnectError::Construct(e.into()))
+        })
+    }
+    /// Connects as `connect` does, but over a session that is resumed by reconnecting should
+    /// the connection be lost, so that remote objects, streams and closures survive it.
+    ///
+    /// The client is kept to make those reconnections, and the server must be listening with
+    /// `Server::listen_resumable`.
+    pub fn connect_resumable<'a, K: Kind, T: Target<'a, K> + 'static, F: Format + 'static>(
+        self,
+        address: Url,
+        resumption: Resumption,
+    ) -> Fallible<K, ConnectError>
+    where
+        F::Representation: Binary + Clone + Sync + Send,
+    {
+        let client = Arc::new(Mutex::new(self.0));
+        let connect = move || {
+            let client = client.clone();
+            let address = address.clone();
+            Box::pin(async move {
+                let connection = client.lock().await.connect(address);
+                let mut connection = connection.await?;
+                Handshake::client::<K>(vec![F::name()])
+                    .exchange(&mut connection)
+                    .await
+                    .map_err(ConnectError::Handshake)?;
+                Ok(connection)
+            }) as Fallible<_, _>
+        };
+        Box::pin(async move {
+            represent(session::connect(connect, resumption).await?)
                 .decode::<T, F>()
                 .await
                 .map_err(|e| ConnectError::Construct(e.into()))
@@ -90,17 +195,43 @@ impl Client {
     }
 }
 
+/// Returns the error of a server asked to listen on an address of a kind it does not support,
+/// such as a websocket server asked to listen on a socket path.
+fn unsupported(address: String) -> ListenError {
+    ListenError {
+        cause: anyhow::anyhow!("this server cannot listen on {}", address),
+    }
+}
+
+/// A server listening on network addresses, socket paths, or both. Servers fail to listen on
+/// those of a kind they do not support.
 #[object]
 pub(crate) trait RawServer {
     fn listen(
         &mut self,
         address: SocketAddr,
-        handler: Box<
+        _handler: Box<
             dyn FnMut(SinkStream<Vec<u8>, ConnectionError, Vec<u8>>) -> Infallible<()>
                 + Sync
                 + Send,
         >,
-    ) -> Fallible<(), ListenError>;
+    ) -> Fallible<(), ListenError> {
+        Box::pin(ready(Err(unsupported(format!("address {}", address)))))
+    }
+    fn listen_path(
+        &mut self,
+        path: String,
+        _handler: Box<
+            dyn FnMut(
+                    SinkStream<Vec<u8>, ConnectionError, Vec<u8>>,
+                    PeerCredentials,
+                ) -> Infallible<()>
+                + Sync
+                + Send,
+        >,
+    ) -> Fallible<(), ListenError> {
+        Box::pin(ready(Err(unsupported(format!("path `{}`", path)))))
+    }
 }
 
 #[derive(Kind)]
@@ -108,55 +239,270 @@ pub struct Server(Box<dyn RawServer>);
 
 impl Server {
     pub fn new() -> Result<Server, UnimplementedError> {
-        RawServer::new().map(Server)
-    }
-    pub fn listen<
-        'a,
-        K: Kind,
-        T: Target<'a, K> + 'static,
-        F: Format<Representation = Vec<u8>> + 'static,
-    >(
+        Server::new_with_keepalive(Keepalive::default())
+    }
+    pub fn new_with_keepalive(keepalive: Keepalive) -> Result<Server, UnimplementedError> {
+        <dyn RawServer>::new(keepalive).map(Server)
+    }
+    /// Returns a server listening for plain TCP connections rather than websockets, to which
+    /// clients connect with a `tcp://` address. This avoids the overhead of websockets where
+    /// no browser need connect.
+    pub fn new_tcp() -> Result<Server, UnimplementedError> {
+        Server::new_tcp_with_keepalive(Keepalive::default())
+    }
+    pub fn new_tcp_with_keepalive(keepalive: Keepalive) -> Result<Server, UnimplementedError> {
+        <dyn RawServer>::new_tcp(keepalive).map(Server)
+    }
+    /// Returns a server listening on a Unix domain socket rather than a network address, for
+    /// processes on the same host to connect to with a `unix://` address. Such a server listens
+    /// with `listen_unix` or `listen_unix_negotiated` only.
+    pub fn new_unix() -> Result<Server, UnimplementedError> {
+        Server::new_unix_with_keepalive(Keepalive::default())
+    }
+    pub fn new_unix_with_keepalive(keepalive: Keepalive) -> Result<Server, UnimplementedError> {
+        <dyn RawServer>::new_unix(keepalive).map(Server)
+    }
+    /// Listens on `address`, serving the `Kind` returned by `handler` to each client that
+    /// connects. Clients must connect with the format `F`.
+    pub fn listen<K: Kind, T: Target<'static, K> + 'static, F: Format + 'static>(
         &mut self,
         address: SocketAddr,
         handler: Box<dyn FnMut() -> Future<K> + Sync + Send>,
     ) -> Fallible<(), ListenError>
     where
-        T: ApplyEncode<'a>,
-        <T as Sink<<T as Context<'a>>::Item>>::Error: std::error::Error + Sync + Send + 'static,
+        F::Representation: Binary + Sync + Send,
+        T: UniformStreamSink<<T as Context<'static>>::Item>,
+        <T as Sink<<T as Context<'static>>::Item>>::Error:
+            std::error::Error + Sync + Send + 'static,
+    {
+        self.listen_negotiated::<K, T>(address, handler, Formats::new().with::<F>())
+    }
+    /// Listens as `listen` does, for clients connecting with `Client::connect_resumable`. The
+    /// handler is called once per session rather than once per connection.
+    pub fn listen_resumable<K: Kind, T: Target<'static, K> + 'static, F: Format + 'static>(
+        &mut self,
+        address: SocketAddr,
+        handler: Box<dyn FnMut() -> Future<K> + Sync + Send>,
+        resumption: Resumption,
+    ) -> Fallible<(), ListenError>
+    where
+        F::Representation: Binary + Sync + Send,
+        T: UniformStreamSink<<T as Context<'static>>::Item>,
+        <T as Sink<<T as Context<'static>>::Item>>::Error:
+            std::error::Error + Sync + Send + 'static,
+    {
+        self.listen_negotiated_resumable::<K, T>(
+            address,
+            handler,
+            Formats::new().with::<F>(),
+            resumption,
+        )
+    }
+    /// Listens as `listen` does, advertising `formats` to each client that connects and serving
+    /// it in the one it chooses. A single server can in this way serve browsers speaking `Json`
+    /// alongside native peers speaking `Bincode`.
+    pub fn listen_negotiated<K: Kind, T: Target<'static, K> + 'static>(
+        &mut self,
+        address: SocketAddr,
+        handler: Box<dyn FnMut() -> Future<K> + Sync + Send>,
+        formats: Formats,
+    ) -> Fallible<(), ListenError>
+    where
+        T: UniformStreamSink<<T as Context<'static>>::Item>,
+        <T as Sink<<T as Context<'static>>::Item>>::Error:
+            std::error::Error + Sync + Send + 'static,
+    {
+        self.listen_with::<K, T>(address, handler, formats, None)
+    }
+    /// Listens as `listen_negotiated` does, for clients connecting with
+    /// `Client::connect_resumable`.
+    pub fn listen_negotiated_resumable<K: Kind, T: Target<'static, K> + 'static>(
+        &mut self,
+        address: SocketAddr,
+        handler: Box<dyn FnMut() -> Future<K> + Sync + Send>,
+        formats: Formats,
+        resumption: Resumption,
+    ) -> Fallible<(), ListenError>
+    where
+        T: UniformStreamSink<<T as Context<'static>>::Item>,
+        <T as Sink<<T as Context<'static>>::Item>>::Error:
+            std::error::Error + Sync + Send + 'static,
+    {
+        self.listen_with::<K, T>(
+            address,
+            handler,
+            formats,
+            Some(session::Server::new(resumption)),
+        )
+    }
+    /// Listens on the Unix domain socket at `path`, serving the `Kind` returned by `handler` to
+    /// each client that connects. The handler is passed the credentials of the connecting
+    /// process, so that it can decide what to serve it. Clients must connect with the format `F`.
+    ///
+    /// A socket left at `path` by a server that is no longer running is replaced.
+    pub fn listen_unix<K: Kind, T: Target<'static, K> + 'static, F: Format + 'static>(
+        &mut self,
+        path: impl AsRef<Path>,
+        handler: Box<dyn FnMut(PeerCredentials) -> Future<K> + Sync + Send>,
+    ) -> Fallible<(), ListenError>
+    where
+        F::Representation: Binary + Sync + Send,
+        T: UniformStreamSink<<T as Context<'static>>::Item>,
+        <T as Sink<<T as Context<'static>>::Item>>::Error:
+            std::error::Error + Sync + Send + 'static,
+    {
+        self.listen_unix_negotiated::<K, T>(path, handler, Formats::new().with::<F>())
+    }
+    /// Listens as `listen_unix` does, advertising `formats` as `listen_negotiated` does.
+    pub fn listen_unix_negotiated<K: Kind, T: Target<'static, K> + 'static>(
+        &mut self,
+        path: impl AsRef<Path>,
+        handler: Box<dyn FnMut(PeerCredentials) -> Future<K> + Sync + Send>,
+        formats: Formats,
+    ) -> Fallible<(), ListenError>
+    where
+        T: UniformStreamSink<<T as Context<'static>>::Item>,
+        <T as Sink<<T as Context<'static>>::Item>>::Error:
+            std::error::Error + Sync + Send + 'static,
+    {
+        // Paths are carried as strings, so those that are not valid Unicode cannot be listened on.
+        let path = match path.as_ref().to_str() {
+            Some(path) => path.to_owned(),
+            None => {
+                return Box::pin(ready(Err(ListenError {
+                    cause: anyhow::anyhow!(
+                        "socket path {} is not valid Unicode",
+                        path.as_ref().display()
+                    ),
+                })))
+            }
+        };
+        let handler = Arc::new(Mutex::new(handler));
+        self.0.listen_path(
+            path,
+            Box::new(move |channel, credentials| {
+                let handler = handler.clone();
+                serve::<K, T>(
+                    channel,
+                    formats.clone(),
+                    None,
+                    Box::new(move || {
+                        Box::pin(async move {
+                            // The handler is only locked to call it, not while it runs.
+                            let handled = (handler.lock().await.as_mut())(credentials);
+                            handled.await
+                        })
+                    }),
+                )
+            }),
+        )
+    }
+    fn listen_with<K: Kind, T: Target<'static, K> + 'static>(
+        &mut self,
+        address: SocketAddr,
+        handler: Box<dyn FnMut() -> Future<K> + Sync + Send>,
+        formats: Formats,
+        sessions: Option<session::Server>,
+    ) -> Fallible<(), ListenError>
+    where
+        T: UniformStreamSink<<T as Context<'static>>::Item>,
+        <T as Sink<<T as Context<'static>>::Item>>::Error:
+            std::error::Error + Sync + Send + 'static,
     {
         let handler = Arc::new(Mutex::new(handler));
         self.0.listen(
             address,
             Box::new(move |channel| {
                 let handler = handler.clone();
-                Box::pin(async move {
-                    let (sender, receiver) = channel.split();
-                    let (sink, stream) = (handler.lock().await.as_mut())()
-                        .await
-                        .on_to::<T>()
-                        .await
-                        .encode::<F>()
-                        .split();
-                    spawn(stream.map(Ok).forward(sender).then(|_| ready(())));
-                    spawn(receiver.map(Ok).forward(sink).then(|_| ready(())));
-                    Ok(())
-                })
+                serve::<K, T>(
+                    channel,
+                    formats.clone(),
+                    sessions.clone(),
+                    Box::new(move || {
+                        Box::pin(async move {
+                            let handled = (handler.lock().await.as_mut())();
+                            handled.await
+                        })
+                    }),
+                )
             }),
         )
     }
 }
 
+/// The time a connecting peer is given to complete the handshake, and to resume its session if
+/// it is resuming one, before it is disconnected.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves a single connection, constructing the `Kind` it is served with `construct` once the
+/// handshake has completed.
+fn serve<K: Kind, T: Target<'static, K> + 'static>(
+    mut channel: SinkStream<Vec<u8>, ConnectionError, Vec<u8>>,
+    formats: Formats,
+    sessions: Option<session::Server>,
+    construct: Box<dyn FnOnce() -> Future<K> + Sync + Send>,
+) -> Infallible<()>
+where
+    T: UniformStreamSink<<T as Context<'static>>::Item>,
+    <T as Sink<<T as Context<'static>>::Item>>::Error: std::error::Error + Sync + Send + 'static,
+{
+    Box::pin(async move {
+        let setup = Box::pin(async move {
+            // A peer that disagrees is sent this end's handshake so that it can report why, and
+            // is then disconnected.
+            let format = Handshake::server::<K>(formats.names())
+                .exchange(&mut channel)
+                .await
+                .ok()
+                .and_then(|name| formats.get(&name))?;
+            // Connections resuming a session carry on with the channel already served.
+            let channel = match sessions {
+                Some(sessions) => sessions.accept(channel).await?,
+                None => channel,
+            };
+            Some((format, channel))
+        });
+        // A peer that never completes the handshake is disconnected rather than held forever.
+        let (format, channel) = match select(setup, Delay::new(HANDSHAKE_TIMEOUT)).await {
+            Either::Left((Some(setup), _)) => setup,
+            _ => return Ok(()),
+        };
+        let (sender, receiver) = channel.split();
+        let target = construct().await.on_to::<T>().await;
+        let (sink, stream) = erased::encode(format, target).split();
+        spawn(stream.map(Ok).forward(sender).then(|_| ready(())));
+        spawn(receiver.map(Ok).forward(sink).then(|_| ready(())));
+        Ok(())
+    })
+}
+
+/// Carries the representation of a format over a connection of bytes. A frame that is not a
+/// valid representation ends the connection.
+fn represent<R: Binary + Sync + Send + 'static>(
+    connection: SinkStream<Vec<u8>, ConnectionError, Vec<u8>>,
+) -> SinkStream<R, ConnectionError, R> {
+    let (sink, stream) = connection.split();
+    SinkStream::new(
+        sink.with(|item: R| ok(item.into_bytes())),
+        stream
+            .map(R::from_bytes)
+            .take_while(|item| ready(item.is_ok()))
+            .filter_map(|item| ready(item.ok())),
+    )
+}
+
 #[cfg(all(not(target_arch = "wasm32"), feature = "core"))]
 mod native;
 #[cfg(all(target_arch = "wasm32", feature = "core"))]
 mod web;
 
 impl dyn RawClient {
-    fn new() -> Result<Box<dyn RawClient>, UnimplementedError> {
+    #[allow(unused_variables)]
+    fn new(keepalive: Keepalive) -> Result<Box<dyn RawClient>, UnimplementedError> {
         #[cfg(all(target_arch = "wasm32", feature = "core"))]
         return Ok(web::Client::new());
         #[cfg(all(not(target_arch = "wasm32"), feature = "core"))]
-        return Ok(native::Client::new());
+        return Ok(native::Client::new(keepalive));
         #[cfg(not(feature = "core"))]
         return Err(UnimplementedError {
             feature: "a network client".to_owned(),
@@ -165,16 +511,37 @@ impl dyn RawClient {
 }
 
 impl dyn RawServer {
-    fn new() -> Result<Box<dyn RawServer>, UnimplementedError> {
+    #[allow(unused_variables)]
+    fn new(keepalive: Keepalive) -> Result<Box<dyn RawServer>, UnimplementedError> {
         #[cfg(all(target_arch = "wasm32", feature = "core"))]
         return Err(UnimplementedError {
             feature: "a network server".to_owned(),
         });
         #[cfg(all(not(target_arch = "wasm32"), feature = "core"))]
-        return Ok(native::Server::new());
+        return Ok(native::Server::new(keepalive));
         #[cfg(not(feature = "core"))]
         return Err(UnimplementedError {
             feature: "a network server".to_owned(),
         });
     }
+
+    #[allow(unused_variables)]
+    fn new_tcp(keepalive: Keepalive) -> Result<Box<dyn RawServer>, UnimplementedError> {
+        #[cfg(all(not(target_arch = "wasm32"), feature = "core"))]
+        return Ok(native::TcpServer::new(keepalive));
+        #[cfg(any(target_arch = "wasm32", not(feature = "core")))]
+        return Err(UnimplementedError {
+            feature: "a TCP server".to_owned(),
+        });
+    }
+
+    #[allow(unused_variables)]
+    fn new_unix(keepalive: Keepalive) -> Result<Box<dyn RawServer>, UnimplementedError> {
+        #[cfg(all(unix, not(target_arch = "wasm32"), feature = "core"))]
+        return Ok(native::UnixServer::new(keepalive));
+        #[cfg(not(all(unix, not(target_arch = "wasm32"), feature = "core")))]
+        return Err(UnimplementedError {
+            feature: "a Unix domain socket server".to_owned(),
+        });
+    }
 }
@@ -1,4 +1,9 @@
-use super::super::{ConnectError, ConnectionError, RawClient};
+#[cfg(unix)]
+use super::unix;
+use super::{
+    super::{ConnectError, ConnectionError, Keepalive, RawClient},
+    tcp, Handler,
+};
 
 use crate::{
     core::spawn,
@@ -15,15 +20,33 @@ use futures::{
 };
 use std::sync::{self, Arc};
 use url::Url;
-use ws::{connect, Message};
+use ws::connect;
 
-pub(crate) struct Client;
+pub(crate) struct Client {
+    keepalive: Keepalive,
+}
 
 impl RawClient for Client {
     fn connect(
         &mut self,
         address: Url,
     ) -> Fallible<SinkStream<Vec<u8>, ConnectionError, Vec<u8>>, ConnectError> {
+        let keepalive = self.keepalive;
+        match address.scheme() {
+            "tcp" => return tcp::connect(address, keepalive),
+            #[cfg(unix)]
+            "unix" => return unix::connect(address, keepalive),
+            "ws" | "wss" => {}
+            scheme => {
+                let scheme = scheme.to_owned();
+                return Box::pin(async move {
+                    Err(ConnectError::Connect(anyhow::anyhow!(
+                        "unsupported scheme `{}`",
+                        scheme
+                    )))
+                });
+            }
+        }
         Box::pin(async move {
             let (out_sender, out_receiver): (_, UnboundedReceiver<Vec<u8>>) = unbounded();
             let out_receiver = Arc::new(Mutex::new(out_receiver));
@@ -31,26 +54,29 @@ impl RawClient for Client {
             let (sender, receiver) = channel();
             let sender = Arc::new(sync::Mutex::new(Some(sender)));
             spawn(async move {
-                connect(address.to_string(), move |peer| {
-                    sender.lock().unwrap().take().unwrap().send(()).unwrap();
-                    let data_sender = data_sender.clone();
+                let opened = sender.clone();
+                let result = connect(address.to_string(), move |peer| {
                     let out_receiver = out_receiver.clone();
+                    let out = peer.clone();
                     spawn(async move {
                         while let Some(item) = out_receiver.lock().await.next().await {
-                            peer.send(item).unwrap();
+                            if out.send(item).is_err() {
+                                break;
+                            }
                         }
                     });
-                    move |message| {
-                        if let Message::Binary(data) = message {
-                            data_sender.clone().start_send(data).unwrap();
-                        }
-                        Ok(())
-                    }
-                })
-                .map_err(|e| ConnectError::Connect(e.into()))
-                .unwrap();
+                    Handler::new(peer, keepalive, data_sender.clone(), Some(opened.clone()))
+                });
+                if let Some(sender) = sender.lock().unwrap().take() {
+                    let _ = sender.send(Err(ConnectError::Connect(match result {
+                        Ok(()) => anyhow::anyhow!("connection to {} was not established", address),
+                        Err(e) => e.into(),
+                    })));
+                }
             });
-            receiver.await.unwrap();
+            receiver
+                .await
+                .map_err(|e| ConnectError::Connect(e.into()))??;
             Ok(SinkStream::new(
                 out_sender.sink_map_err(|e| ConnectionError { cause: e.into() }),
                 data_receiver,
@@ -60,7 +86,7 @@ impl RawClient for Client {
 }
 
 impl Client {
-    pub(crate) fn new() -> Box<dyn RawClient> {
-        Box::new(Client)
+    pub(crate) fn new(keepalive: Keepalive) -> Box<dyn RawClient> {
+        Box::new(Client { keepalive })
     }
 }
@@ -2,3 +2,181 @@ mod server;
 pub(crate) use server::Server;
 mod client;
 pub(crate) use client::Client;
+mod stream;
+mod tcp;
+pub(crate) use tcp::Server as TcpServer;
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+pub(crate) use unix::Server as UnixServer;
+
+use super::{ConnectError, Keepalive};
+
+use crate::{core::spawn, kind::Infallible};
+
+use futures::{
+    channel::{mpsc::UnboundedSender, oneshot::Sender},
+    lock::Mutex,
+};
+use std::sync::{self, Arc};
+use ws::{
+    util::{Timeout, Token},
+    CloseCode, Frame, Handshake, Message,
+};
+
+const PING: Token = Token(1);
+const EXPIRE: Token = Token(2);
+
+type Opened = Arc<sync::Mutex<Option<Sender<Result<(), ConnectError>>>>>;
+
+/// Hands the connections accepted by a server to its handler.
+///
+/// The handler is only held while a connection is handed to it, so that a slow connection never
+/// holds up the next.
+struct Dispatcher<A>(Arc<Mutex<Box<dyn FnMut(A) -> Infallible<()> + Sync + Send>>>);
+
+impl<A> Clone for Dispatcher<A> {
+    fn clone(&self) -> Self {
+        Dispatcher(self.0.clone())
+    }
+}
+
+impl<A: Sync + Send + 'static> Dispatcher<A> {
+    fn new(handler: impl FnMut(A) -> Infallible<()> + Sync + Send + 'static) -> Self {
+        Dispatcher(Arc::new(Mutex::new(Box::new(handler))))
+    }
+
+    fn dispatch(&self, connection: A) {
+        let handler = self.0.clone();
+        spawn(async move {
+            let serve = (handler.lock().await.as_mut())(connection);
+            serve.await.unwrap();
+        });
+    }
+}
+
+/// Handles one end of a websocket connection, forwarding binary messages to `data_sender` and
+/// closing it once the connection is closed or the peer has been silent for longer than the
+/// keepalive timeout.
+struct Handler {
+    out: ws::Sender,
+    keepalive: Keepalive,
+    data_sender: UnboundedSender<Vec<u8>>,
+    opened: Option<Opened>,
+    ping: Option<Timeout>,
+    expire: Option<Timeout>,
+}
+
+impl Handler {
+    fn new(
+        out: ws::Sender,
+        keepalive: Keepalive,
+        data_sender: UnboundedSender<Vec<u8>>,
+        opened: Option<Opened>,
+    ) -> Self {
+        // The opening handshake of a client is bounded by the keepalive timeout, as a server
+        // that closes the connection during it is otherwise never noticed.
+        if opened.is_some() {
+            let _ = out.timeout(keepalive.timeout.as_millis() as u64, EXPIRE);
+        }
+        Handler {
+            out,
+            keepalive,
+            data_sender,
+            opened,
+            ping: None,
+            expire: None,
+        }
+    }
+
+    fn cancel(&mut self) -> ws::Result<()> {
+        if let Some(timeout) = self.ping.take() {
+            self.out.cancel(timeout)?;
+        }
+        if let Some(timeout) = self.expire.take() {
+            self.out.cancel(timeout)?;
+        }
+        Ok(())
+    }
+}
+
+impl ws::Handler for Handler {
+    fn on_open(&mut self, _: Handshake) -> ws::Result<()> {
+        if let Some(sender) = self
+            .opened
+            .as_ref()
+            .and_then(|opened| opened.lock().unwrap().take())
+        {
+            let _ = sender.send(Ok(()));
+        }
+        self.out
+            .timeout(self.keepalive.interval.as_millis() as u64, PING)?;
+        self.out
+            .timeout(self.keepalive.timeout.as_millis() as u64, EXPIRE)
+    }
+    fn on_message(&mut self, message: Message) -> ws::Result<()> {
+        if let Message::Binary(data) = message {
+            let _ = self.data_sender.unbounded_send(data);
+        }
+        Ok(())
+    }
+    fn on_frame(&mut self, frame: Frame) -> ws::Result<Option<Frame>> {
+        // Any frame at all, pongs included, shows that the peer is still there.
+        if let Some(timeout) = self.expire.take() {
+            self.out.cancel(timeout)?;
+        }
+        self.out
+            .timeout(self.keepalive.timeout.as_millis() as u64, EXPIRE)?;
+        Ok(Some(frame))
+    }
+    fn on_timeout(&mut self, event: Token) -> ws::Result<()> {
+        match event {
+            PING => {
+                self.out.ping(vec![])?;
+                self.out
+                    .timeout(self.keepalive.interval.as_millis() as u64, PING)
+            }
+            EXPIRE => {
+                self.cancel()?;
+                if let Some(sender) = self
+                    .opened
+                    .as_ref()
+                    .and_then(|opened| opened.lock().unwrap().take())
+                {
+                    let _ = sender.send(Err(ConnectError::Connect(anyhow::anyhow!(
+                        "opening handshake timed out"
+                    ))));
+                    return self.out.shutdown();
+                }
+                self.data_sender.close_channel();
+                self.out.close(CloseCode::Away)
+            }
+            _ => Ok(()),
+        }
+    }
+    fn on_new_timeout(&mut self, event: Token, timeout: Timeout) -> ws::Result<()> {
+        let previous = match event {
+            PING => self.ping.replace(timeout),
+            EXPIRE => self.expire.replace(timeout),
+            _ => None,
+        };
+        if let Some(timeout) = previous {
+            self.out.cancel(timeout)?;
+        }
+        Ok(())
+    }
+    fn on_close(&mut self, _: CloseCode, _: &str) {
+        let _ = self.cancel();
+        self.data_sender.close_channel();
+    }
+    fn on_error(&mut self, error: ws::Error) {
+        // Errors before the handshake completes mean the connection was never established.
+        if let Some(sender) = self
+            .opened
+            .as_ref()
+            .and_then(|opened| opened.lock().unwrap().take())
+        {
+            let _ = sender.send(Err(ConnectError::Connect(error.into())));
+        }
+    }
+}
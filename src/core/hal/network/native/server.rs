@@ -1,15 +1,20 @@
-use super::super::{ConnectionError, ListenError, RawServer};
+use super::{
+    super::{ConnectionError, Keepalive, ListenError, RawServer},
+    Dispatcher, Handler,
+};
 
 use crate::{
     core::spawn,
     kind::{Fallible, Infallible, SinkStream},
 };
 
-use futures::{channel::mpsc::unbounded, lock::Mutex, SinkExt, StreamExt};
-use std::{net::SocketAddr, sync::Arc};
-use ws::{listen, Message};
+use futures::{channel::mpsc::unbounded, SinkExt, StreamExt};
+use std::net::SocketAddr;
+use ws::listen;
 
-pub(crate) struct Server;
+pub(crate) struct Server {
+    keepalive: Keepalive,
+}
 
 impl RawServer for Server {
     fn listen(
@@ -21,31 +26,28 @@ impl RawServer for Server {
                 + Send,
         >,
     ) -> Fallible<(), ListenError> {
+        let keepalive = self.keepalive;
         Box::pin(async move {
-            let handler = Arc::new(Mutex::new(handler));
+            let dispatcher = Dispatcher::new(handler);
             listen(address, move |peer| {
-                let handler = handler.clone();
+                let dispatcher = dispatcher.clone();
                 let (sender, receiver) = unbounded();
+                let out = peer.clone();
                 spawn(async move {
                     let (data_sender, mut stream) = unbounded();
                     spawn(async move {
                         while let Some(item) = stream.next().await {
-                            peer.send(item).unwrap();
+                            if out.send(item).is_err() {
+                                break;
+                            }
                         }
                     });
-                    (handler.lock().await.as_mut())(SinkStream::new(
+                    dispatcher.dispatch(SinkStream::new(
                         data_sender.sink_map_err(|e| ConnectionError { cause: e.into() }),
                         receiver,
-                    ))
-                    .await
-                    .unwrap();
+                    ));
                 });
-                move |message| {
-                    if let Message::Binary(data) = message {
-                        sender.clone().start_send(data).unwrap();
-                    }
-                    Ok(())
-                }
+                Handler::new(peer, keepalive, sender, None)
             })
             .map_err(|e| ListenError { cause: e.into() })?;
             Ok(())
@@ -54,7 +56,7 @@ impl RawServer for Server {
 }
 
 impl Server {
-    pub(crate) fn new() -> Box<dyn RawServer> {
-        Box::new(Server)
+    pub(crate) fn new(keepalive: Keepalive) -> Box<dyn RawServer> {
+        Box::new(Server { keepalive })
     }
 }
@@ -0,0 +1,144 @@
+use super::{
+    super::{ConnectionError, Keepalive},
+    Dispatcher,
+};
+
+use crate::{
+    channel::BufferLimits,
+    format::Framing,
+    kind::{Future, SinkStream},
+};
+
+use futures::{
+    channel::{
+        mpsc::{channel, Receiver, Sender},
+        oneshot,
+    },
+    executor::block_on,
+    future::{select, Either},
+    io::{empty, sink, AllowStdIo},
+    SinkExt, StreamExt,
+};
+use futures_timer::Delay;
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    thread,
+    time::Duration,
+};
+
+/// A blocking byte-stream socket over which frames can be carried.
+pub(crate) trait Socket: Read + Write + Sized + Sync + Send + 'static {
+    fn try_clone(&self) -> io::Result<Self>;
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+    fn shutdown(&self, how: Shutdown) -> io::Result<()>;
+}
+
+impl Socket for TcpStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        TcpStream::shutdown(self, how)
+    }
+}
+
+#[cfg(unix)]
+impl Socket for std::os::unix::net::UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        std::os::unix::net::UnixStream::try_clone(self)
+    }
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        std::os::unix::net::UnixStream::set_read_timeout(self, timeout)
+    }
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        std::os::unix::net::UnixStream::shutdown(self, how)
+    }
+}
+
+/// Carries the frames of a connection over `stream`, with a thread reading from it and another
+/// writing to it so that the executor is never blocked on the socket.
+///
+/// An empty frame is written whenever nothing else has been for the keepalive interval, and is
+/// discarded by the peer. The connection is considered lost if nothing at all is read for the
+/// keepalive timeout. At most as many frames as a connection buffers are held in either
+/// direction, beyond which sending blocks until the writer catches up, and the reader blocks
+/// until those it has read are taken.
+pub(crate) fn carry<S: Socket>(
+    stream: S,
+    keepalive: Keepalive,
+) -> io::Result<SinkStream<Vec<u8>, ConnectionError, Vec<u8>>> {
+    stream.set_read_timeout(Some(keepalive.timeout))?;
+    let reader = stream.try_clone()?;
+    let limits = BufferLimits::default();
+    let (out_sender, out_receiver) = channel(limits.connection);
+    let (data_sender, data_receiver) = channel(limits.connection);
+    thread::spawn(move || read(reader, data_sender));
+    thread::spawn(move || write(stream, out_receiver, keepalive));
+    Ok(SinkStream::new(
+        out_sender.sink_map_err(|e| ConnectionError { cause: e.into() }),
+        data_receiver,
+    ))
+}
+
+fn read<S: Socket>(stream: S, mut data_sender: Sender<Vec<u8>>) {
+    let shutdown = stream.try_clone();
+    block_on(async move {
+        let mut frames = Framing::default().framed_split(AllowStdIo::new(stream), sink());
+        while let Some(frame) = frames.next().await {
+            if !frame.is_empty() && data_sender.send(frame).await.is_err() {
+                break;
+            }
+        }
+    });
+    // The connection is lost to the writer as well, which is otherwise only noticed the next
+    // time it writes.
+    if let Ok(stream) = shutdown {
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn write<S: Socket>(stream: S, mut out_receiver: Receiver<Vec<u8>>, keepalive: Keepalive) {
+    let shutdown = stream.try_clone();
+    block_on(async move {
+        let mut frames = Framing::default().framed_split(empty(), AllowStdIo::new(stream));
+        loop {
+            let frame = match select(out_receiver.next(), Delay::new(keepalive.interval)).await {
+                Either::Left((Some(frame), _)) => frame,
+                Either::Left((None, _)) => break,
+                Either::Right(_) => vec![],
+            };
+            if frames.send(frame).await.is_err() {
+                break;
+            }
+        }
+    });
+    if let Ok(stream) = shutdown {
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Accepts sockets with `accept` on a thread of its own, setting each up as a connection with
+/// `setup` and handing it to `dispatcher`. Failing to accept or set up one connection leaves
+/// the others unaffected.
+pub(super) fn serve<S, A: Sync + Send + 'static>(
+    mut accept: impl FnMut() -> io::Result<S> + Send + 'static,
+    mut setup: impl FnMut(S) -> io::Result<A> + Send + 'static,
+    dispatcher: Dispatcher<A>,
+) -> Future<()> {
+    let (done, stopped) = oneshot::channel::<()>();
+    thread::spawn(move || {
+        let _done = done;
+        loop {
+            if let Ok(connection) = accept().and_then(&mut setup) {
+                dispatcher.dispatch(connection);
+            }
+        }
+    });
+    Box::pin(async move {
+        let _ = stopped.await;
+    })
+}
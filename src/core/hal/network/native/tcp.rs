@@ -0,0 +1,87 @@
+use super::{
+    super::{ConnectError, ConnectionError, Keepalive, ListenError, RawServer},
+    stream, Dispatcher,
+};
+
+use crate::kind::{Fallible, Infallible, SinkStream};
+
+use futures::channel::oneshot::channel;
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread,
+};
+use url::Url;
+
+fn carry(
+    stream: TcpStream,
+    keepalive: Keepalive,
+) -> io::Result<SinkStream<Vec<u8>, ConnectionError, Vec<u8>>> {
+    stream.set_nodelay(true)?;
+    stream::carry(stream, keepalive)
+}
+
+/// Connects to a `tcp://` address, resolving its host and trying each of its addresses in turn.
+pub(crate) fn connect(
+    address: Url,
+    keepalive: Keepalive,
+) -> Fallible<SinkStream<Vec<u8>, ConnectionError, Vec<u8>>, ConnectError> {
+    Box::pin(async move {
+        let (sender, receiver) = channel();
+        // Both resolving the host and connecting block, so are kept off the executor.
+        thread::spawn(move || {
+            let _ = sender.send(address.socket_addrs(|| None).and_then(|addresses| {
+                let mut error = io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} did not resolve to any address", address),
+                );
+                for address in addresses {
+                    match TcpStream::connect_timeout(&address, keepalive.timeout) {
+                        Ok(stream) => return carry(stream, keepalive),
+                        Err(e) => error = e,
+                    }
+                }
+                Err(error)
+            }));
+        });
+        receiver
+            .await
+            .map_err(|e| ConnectError::Connect(e.into()))?
+            .map_err(|e| ConnectError::Connect(e.into()))
+    })
+}
+
+pub(crate) struct Server {
+    keepalive: Keepalive,
+}
+
+impl RawServer for Server {
+    fn listen(
+        &mut self,
+        address: SocketAddr,
+        handler: Box<
+            dyn FnMut(SinkStream<Vec<u8>, ConnectionError, Vec<u8>>) -> Infallible<()>
+                + Sync
+                + Send,
+        >,
+    ) -> Fallible<(), ListenError> {
+        let keepalive = self.keepalive;
+        Box::pin(async move {
+            let listener =
+                TcpListener::bind(address).map_err(|e| ListenError { cause: e.into() })?;
+            stream::serve(
+                move || listener.accept().map(|(stream, _)| stream),
+                move |stream| carry(stream, keepalive),
+                Dispatcher::new(handler),
+            )
+            .await;
+            Ok(())
+        })
+    }
+}
+
+impl Server {
+    pub(crate) fn new(keepalive: Keepalive) -> Box<dyn RawServer> {
+        Box::new(Server { keepalive })
+    }
+}
@@ -0,0 +1,144 @@
+use super::{
+    super::{ConnectError, ConnectionError, Keepalive, ListenError, PeerCredentials, RawServer},
+    stream::{self, carry},
+    Dispatcher,
+};
+
+use crate::kind::{Fallible, Infallible, SinkStream};
+
+use futures::channel::oneshot::channel;
+use std::{
+    fs, io,
+    os::unix::{
+        fs::FileTypeExt,
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    thread,
+};
+use url::Url;
+
+/// Returns the credentials of the process at the other end of `stream`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn credentials(stream: &UnixStream) -> io::Result<PeerCredentials> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(PeerCredentials {
+        uid: credentials.uid,
+        gid: credentials.gid,
+        pid: Some(credentials.pid),
+    })
+}
+
+/// Returns the credentials of the process at the other end of `stream`. The process id of the
+/// peer is not available on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn credentials(stream: &UnixStream) -> io::Result<PeerCredentials> {
+    let mut uid = 0;
+    let mut gid = 0;
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(PeerCredentials {
+        uid,
+        gid,
+        pid: None,
+    })
+}
+
+/// Connects to a `unix://` address, the path of which is that of the socket.
+pub(crate) fn connect(
+    address: Url,
+    keepalive: Keepalive,
+) -> Fallible<SinkStream<Vec<u8>, ConnectionError, Vec<u8>>, ConnectError> {
+    Box::pin(async move {
+        let path = address.to_file_path().map_err(|_| {
+            ConnectError::Connect(anyhow::anyhow!("{} is not a socket path", address))
+        })?;
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let _ =
+                sender.send(UnixStream::connect(path).and_then(|stream| carry(stream, keepalive)));
+        });
+        receiver
+            .await
+            .map_err(|e| ConnectError::Connect(e.into()))?
+            .map_err(|e| ConnectError::Connect(e.into()))
+    })
+}
+
+/// Removes a socket left at `path` by a server that has since exited, which would otherwise
+/// prevent binding to it. Anything other than a socket is left in place.
+fn remove_stale(path: &str) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("a server is already listening on `{}`", path),
+                ));
+            }
+            fs::remove_file(path)
+        }
+        _ => Ok(()),
+    }
+}
+
+pub(crate) struct Server {
+    keepalive: Keepalive,
+}
+
+impl RawServer for Server {
+    fn listen_path(
+        &mut self,
+        path: String,
+        handler: Box<
+            dyn FnMut(
+                    SinkStream<Vec<u8>, ConnectionError, Vec<u8>>,
+                    PeerCredentials,
+                ) -> Infallible<()>
+                + Sync
+                + Send,
+        >,
+    ) -> Fallible<(), ListenError> {
+        let keepalive = self.keepalive;
+        Box::pin(async move {
+            let listener = remove_stale(&path)
+                .and_then(|_| UnixListener::bind(&path))
+                .map_err(|e| ListenError { cause: e.into() })?;
+            let mut handler = handler;
+            stream::serve(
+                move || listener.accept().map(|(stream, _)| stream),
+                move |stream| {
+                    let credentials = credentials(&stream)?;
+                    Ok((carry(stream, keepalive)?, credentials))
+                },
+                Dispatcher::new(move |(connection, credentials)| handler(connection, credentials)),
+            )
+            .await;
+            Ok(())
+        })
+    }
+}
+
+impl Server {
+    pub(crate) fn new(keepalive: Keepalive) -> Box<dyn RawServer> {
+        Box::new(Server { keepalive })
+    }
+}
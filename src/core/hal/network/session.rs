@@ -0,0 +1,453 @@
+//! Sessions that outlive the connections carrying them.
+//!
+//! A session numbers every frame sent over it and keeps each one until the peer acknowledges
+//! it. Should the underlying connection be lost, the client reconnects and resumes the session
+//! by its identifier, after which both ends replay whatever the other has not yet received. The
+//! frames seen by the `Format` and channel above are unaffected, so forks stay valid across
+//! reconnects.
+
+use super::{ConnectError, ConnectionError, HandshakeError};
+
+use crate::{
+    channel::BufferLimits,
+    core::{hal::crypto::Rng, spawn},
+    kind::{Fallible, SinkStream},
+};
+
+use futures::{
+    channel::mpsc::{channel, Sender},
+    future::{select, Either},
+    lock::Mutex,
+    stream::SplitSink,
+    SinkExt, StreamExt,
+};
+use futures_timer::Delay;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{self, Arc},
+    time::Duration,
+};
+
+type Connection = SinkStream<Vec<u8>, ConnectionError, Vec<u8>>;
+
+type Sessions = Arc<sync::Mutex<HashMap<[u8; 32], Session>>>;
+
+/// The number of frames received without sending anything in return after which they are
+/// acknowledged explicitly.
+const ACK_INTERVAL: u64 = 16;
+
+/// The number of frames sent over a session that may go unacknowledged, beyond which sending
+/// waits for the peer to acknowledge them.
+const UNACKED: usize = 1024;
+
+/// How sessions are resumed after the connection carrying them is lost.
+///
+/// A client waits `backoff` before its first attempt to reconnect, doubling the delay after
+/// each failed attempt up to `max_backoff`. Either end gives the session up once it has gone
+/// without a connection for `timeout`, or without the peer acknowledging any of the frames it
+/// has been sent once too many of them are unacknowledged, at which point every remote `Future`
+/// and `Stream` on it resolves with a `TransportError`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resumption {
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for Resumption {
+    fn default() -> Self {
+        Resumption {
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+/// Sent by the client once the handshake has completed.
+#[derive(Serialize, Deserialize)]
+enum Hello {
+    Open,
+    Resume { session: [u8; 32], received: u64 },
+}
+
+/// Sent by the server in reply to a `Hello`, with the sequence number of the last frame it
+/// received on the session.
+#[derive(Serialize, Deserialize)]
+enum Welcome {
+    Accepted { session: [u8; 32], received: u64 },
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Packet {
+    Data {
+        sequence: u64,
+        ack: u64,
+        data: Vec<u8>,
+    },
+    Ack(u64),
+    Close,
+}
+
+fn encode<T: Serialize>(item: &T) -> Vec<u8> {
+    serde_cbor::to_vec(item).unwrap()
+}
+
+async fn receive<T: for<'de> Deserialize<'de>>(
+    connection: &mut Connection,
+) -> Result<T, HandshakeError> {
+    let item = connection.next().await.ok_or(HandshakeError::Closed)?;
+    serde_cbor::from_slice(&item).map_err(|_| HandshakeError::Malformed)
+}
+
+struct State {
+    transport: Option<SplitSink<Connection, Vec<u8>>>,
+    generation: u64,
+    sent: u64,
+    unacked: VecDeque<(u64, Vec<u8>)>,
+    /// Signalled whenever frames are acknowledged, for sending to resume once there is room.
+    acked: Sender<()>,
+    received: u64,
+    acknowledged: u64,
+    closed: bool,
+    data: Sender<Vec<u8>>,
+}
+
+impl State {
+    fn acknowledge(&mut self, ack: u64) {
+        let unacked = self.unacked.len();
+        while self
+            .unacked
+            .front()
+            .map(|(sequence, _)| *sequence <= ack)
+            .unwrap_or(false)
+        {
+            self.unacked.pop_front();
+        }
+        if self.unacked.len() < unacked {
+            let _ = self.acked.try_send(());
+        }
+    }
+
+    async fn send(&mut self, packet: Packet) {
+        if let Some(transport) = self.transport.as_mut() {
+            if transport.send(encode(&packet)).await.is_err() {
+                self.transport = None;
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Session {
+    id: [u8; 32],
+    state: Arc<Mutex<State>>,
+}
+
+impl Session {
+    /// Creates a session, returning it along with the connection carried by it. The session is
+    /// given up should the peer leave too many frames unacknowledged for `timeout`.
+    fn new(id: [u8; 32], timeout: Duration) -> (Self, Connection) {
+        let limits = BufferLimits::default();
+        let (out_sender, mut out_receiver) = channel::<Vec<u8>>(limits.connection);
+        let (data_sender, data_receiver) = channel(limits.connection);
+        let (acked, mut acknowledged) = channel(0);
+        let session = Session {
+            id,
+            state: Arc::new(Mutex::new(State {
+                transport: None,
+                generation: 0,
+                sent: 0,
+                unacked: VecDeque::new(),
+                acked,
+                received: 0,
+                acknowledged: 0,
+                closed: false,
+                data: data_sender,
+            })),
+        };
+        let sender = session.clone();
+        spawn(async move {
+            while let Some(data) = out_receiver.next().await {
+                let mut state = loop {
+                    let state = sender.state.lock().await;
+                    if state.closed {
+                        return;
+                    }
+                    if state.unacked.len() < UNACKED {
+                        break state;
+                    }
+                    drop(state);
+                    if let Either::Right(_) = select(acknowledged.next(), Delay::new(timeout)).await
+                    {
+                        sender.close(true).await;
+                        return;
+                    }
+                };
+                state.sent += 1;
+                let (sequence, ack) = (state.sent, state.received);
+                state.unacked.push_back((sequence, data.clone()));
+                state.acknowledged = ack;
+                state
+                    .send(Packet::Data {
+                        sequence,
+                        ack,
+                        data,
+                    })
+                    .await;
+            }
+            sender.close(true).await;
+        });
+        (
+            session,
+            SinkStream::new(
+                out_sender.sink_map_err(|e| ConnectionError { cause: e.into() }),
+                data_receiver,
+            ),
+        )
+    }
+
+    async fn close(&self, notify: bool) {
+        let mut state = self.state.lock().await;
+        if state.closed {
+            return;
+        }
+        state.closed = true;
+        if notify {
+            state.send(Packet::Close).await;
+        }
+        if let Some(mut transport) = state.transport.take() {
+            let _ = transport.close().await;
+        }
+        state.data.close_channel();
+    }
+
+    async fn received(&self) -> u64 {
+        self.state.lock().await.received
+    }
+
+    /// Carries the session over `connection` until it is lost, having first replayed every
+    /// frame sent after `received`, the last one the peer has. Returns the generation of the
+    /// connection, which identifies it to `expire`.
+    async fn attach(&self, connection: Connection, received: u64) -> u64 {
+        let (mut sink, mut stream) = connection.split();
+        let generation = {
+            let mut state = self.state.lock().await;
+            if state.closed {
+                return state.generation;
+            }
+            state.acknowledge(received);
+            let ack = state.received;
+            for (sequence, data) in state.unacked.clone() {
+                let packet = Packet::Data {
+                    sequence,
+                    ack,
+                    data,
+                };
+                if sink.send(encode(&packet)).await.is_err() {
+                    return state.generation;
+                }
+            }
+            state.acknowledged = ack;
+            state.transport = Some(sink);
+            state.generation += 1;
+            state.generation
+        };
+        while let Some(packet) = stream.next().await {
+            let mut state = self.state.lock().await;
+            if state.generation != generation || state.closed {
+                break;
+            }
+            match serde_cbor::from_slice(&packet) {
+                Ok(Packet::Data {
+                    sequence,
+                    ack,
+                    data,
+                }) => {
+                    state.acknowledge(ack);
+                    // Frames at or before `received` are replays of ones already delivered.
+                    if sequence == state.received + 1 {
+                        state.received = sequence;
+                        if state.received - state.acknowledged >= ACK_INTERVAL {
+                            let ack = state.received;
+                            state.acknowledged = ack;
+                            state.send(Packet::Ack(ack)).await;
+                        }
+                        // Frames are only read from the connection as fast as they are taken.
+                        let mut delivered = state.data.clone();
+                        drop(state);
+                        let _ = delivered.send(data).await;
+                    } else if sequence > state.received {
+                        break;
+                    }
+                }
+                Ok(Packet::Ack(ack)) => state.acknowledge(ack),
+                Ok(Packet::Close) => {
+                    drop(state);
+                    self.close(false).await;
+                    return generation;
+                }
+                Err(_) => break,
+            }
+        }
+        let mut state = self.state.lock().await;
+        if state.generation == generation {
+            state.transport = None;
+        }
+        generation
+    }
+
+    /// Closes the session if no connection has replaced the one of `generation` once `timeout`
+    /// has elapsed.
+    async fn expire(&self, generation: u64, timeout: Duration) {
+        Delay::new(timeout).await;
+        if self.state.lock().await.generation == generation {
+            self.close(false).await;
+        }
+    }
+}
+
+/// Opens a session over connections made by `connect`, reconnecting and resuming it whenever
+/// the current connection is lost.
+pub(crate) async fn connect(
+    connect: impl Fn() -> Fallible<Connection, ConnectError> + Sync + Send + 'static,
+    resumption: Resumption,
+) -> Result<Connection, ConnectError> {
+    let mut connection = connect().await?;
+    connection
+        .send(encode(&Hello::Open))
+        .await
+        .map_err(|e| ConnectError::Connect(e.into()))?;
+    let id = match receive(&mut connection)
+        .await
+        .map_err(ConnectError::Handshake)?
+    {
+        Welcome::Accepted { session, .. } => session,
+        Welcome::Unknown => return Err(ConnectError::Handshake(HandshakeError::Malformed)),
+    };
+    let (session, channel) = Session::new(id, resumption.timeout);
+    spawn(async move {
+        let mut received = 0;
+        loop {
+            session.attach(connection, received).await;
+            if session.state.lock().await.closed {
+                return;
+            }
+            let resumed = select(
+                Box::pin(resume(&connect, &session, resumption)),
+                Delay::new(resumption.timeout),
+            )
+            .await;
+            match resumed {
+                Either::Left((Some(resumed), _)) => {
+                    connection = resumed.0;
+                    received = resumed.1;
+                }
+                _ => {
+                    session.close(false).await;
+                    return;
+                }
+            }
+        }
+    });
+    Ok(channel)
+}
+
+/// Reconnects with backoff until `session` is resumed, returning the new connection and the
+/// sequence number of the last frame the server received, or `None` if the server has given the
+/// session up.
+async fn resume(
+    connect: &impl Fn() -> Fallible<Connection, ConnectError>,
+    session: &Session,
+    resumption: Resumption,
+) -> Option<(Connection, u64)> {
+    let mut backoff = resumption.backoff;
+    loop {
+        Delay::new(backoff).await;
+        backoff = (backoff * 2).min(resumption.max_backoff);
+        let mut connection = match connect().await {
+            Ok(connection) => connection,
+            Err(_) => continue,
+        };
+        let hello = Hello::Resume {
+            session: session.id,
+            received: session.received().await,
+        };
+        if connection.send(encode(&hello)).await.is_err() {
+            continue;
+        }
+        match receive(&mut connection).await {
+            Ok(Welcome::Accepted { received, .. }) => return Some((connection, received)),
+            Ok(Welcome::Unknown) => return None,
+            Err(_) => continue,
+        }
+    }
+}
+
+/// The sessions opened on a server.
+#[derive(Clone)]
+pub(crate) struct Server {
+    sessions: Sessions,
+    resumption: Resumption,
+}
+
+impl Server {
+    pub(crate) fn new(resumption: Resumption) -> Self {
+        Server {
+            sessions: Sessions::default(),
+            resumption,
+        }
+    }
+
+    /// Opens or resumes a session over `connection`, returning the connection carried by the
+    /// session if it is a new one.
+    pub(crate) async fn accept(&self, mut connection: Connection) -> Option<Connection> {
+        match receive(&mut connection).await.ok()? {
+            Hello::Open => {
+                let bytes = <dyn Rng>::new().ok()?.bytes(32).await.ok()?;
+                let mut id = [0u8; 32];
+                id.copy_from_slice(&bytes);
+                let welcome = Welcome::Accepted {
+                    session: id,
+                    received: 0,
+                };
+                connection.send(encode(&welcome)).await.ok()?;
+                let (session, channel) = Session::new(id, self.resumption.timeout);
+                self.sessions.lock().unwrap().insert(id, session.clone());
+                self.carry(session, connection, 0);
+                Some(channel)
+            }
+            Hello::Resume { session, received } => {
+                let session = self.sessions.lock().unwrap().get(&session).cloned();
+                match session {
+                    Some(session) => {
+                        let welcome = Welcome::Accepted {
+                            session: session.id,
+                            received: session.received().await,
+                        };
+                        connection.send(encode(&welcome)).await.ok()?;
+                        self.carry(session, connection, received);
+                    }
+                    None => {
+                        let _ = connection.send(encode(&Welcome::Unknown)).await;
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    fn carry(&self, session: Session, connection: Connection, received: u64) {
+        let sessions = self.sessions.clone();
+        let timeout = self.resumption.timeout;
+        spawn(async move {
+            let generation = session.attach(connection, received).await;
+            session.expire(generation, timeout).await;
+            if session.state.lock().await.closed {
+                sessions.lock().unwrap().remove(&session.id);
+            }
+        });
+    }
+}
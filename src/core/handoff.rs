@@ -0,0 +1,253 @@
+//! Three-party handoff of `#[object]` trait objects.
+//!
+//! A node that has called `advertise` exports every object it sends under a random token, and
+//! the object carries a `Reference` to that export wherever it is sent on to. When a node
+//! receives an object from a node other than its owner, it connects to the owner's `Vat` using
+//! the client passed to `route` and acquires the object directly. The forwarding node's proxy is
+//! used as a fallback if no route exists, and is dropped otherwise.
+
+use crate::{
+    channel::IdChannel,
+    core::hal::{crypto::Rng, network::Client},
+    format::{ApplyDecode, ApplyEncode, Cbor},
+    kind::{using, Fallible, Future, SinkStream, TransportError},
+    object, Kind, OnTo,
+};
+
+use alloc::sync::{Arc, Weak};
+use anyhow::Error;
+use core::any::Any;
+use futures::{future::ok, SinkExt, StreamExt};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex};
+use thiserror::Error;
+use url::Url;
+
+/// A reference to an object that is meaningful beyond the channel it was received on: the
+/// address of the node that owns the object and the token under which that node exports it.
+#[derive(Serialize, Deserialize, Kind, Debug, Clone, PartialEq, Eq, Hash)]
+#[kind(using::Serde)]
+pub struct Reference {
+    address: String,
+    token: [u8; 32],
+}
+
+#[derive(Error, Debug, Kind)]
+pub enum HandoffError {
+    #[error("no object is exported under the given token")]
+    Unexported,
+    #[error("no route to the owner of the object")]
+    Unroutable,
+    #[error("connection to the owner failed: {0}")]
+    Connect(#[source] Error),
+    #[error("construct failed: {0}")]
+    Construct(#[source] Error),
+    #[error("underlying transport failed: {0}")]
+    #[kind(lost)]
+    Transport(#[from] TransportError),
+}
+
+/// A frame of an acquired object's encoded connection. Bytes are sent as a single item rather
+/// than as the `Kind` of `Vec<u8>`, which would fork for every byte.
+#[derive(Serialize, Deserialize, Kind)]
+#[kind(using::Serde)]
+struct Frame(Vec<u8>);
+
+type Connection = SinkStream<Frame, Error, Frame>;
+
+type Local = Box<dyn Fn(Arc<dyn Any + Sync + Send>) -> Box<dyn Any + Sync + Send> + Sync + Send>;
+
+type Acquire =
+    Box<dyn Fn(Arc<dyn Any + Sync + Send>) -> Fallible<Connection, HandoffError> + Sync + Send>;
+
+/// An exported object, kept in the export table for as long as any proxy of it is alive.
+struct Export {
+    token: [u8; 32],
+    local: Local,
+    acquire: Acquire,
+}
+
+impl Drop for Export {
+    fn drop(&mut self) {
+        EXPORTS.lock().unwrap().objects.remove(&self.token);
+    }
+}
+
+#[derive(Default)]
+struct Exports {
+    address: Option<String>,
+    objects: HashMap<[u8; 32], Weak<Export>>,
+}
+
+#[derive(Default)]
+struct Routes {
+    client: Option<Client>,
+    vats: HashMap<String, Arc<Vat>>,
+}
+
+lazy_static! {
+    static ref EXPORTS: Mutex<Exports> = Mutex::new(Exports::default());
+    static ref ROUTES: Mutex<Routes> = Mutex::new(Routes::default());
+}
+
+fn get(token: &[u8; 32]) -> Option<Arc<Export>> {
+    EXPORTS
+        .lock()
+        .unwrap()
+        .objects
+        .get(token)
+        .and_then(Weak::upgrade)
+}
+
+/// Exports objects sent from this node under references to `address`, at which a `Vat` should
+/// be served so that the holders of those references can reach them directly.
+pub fn advertise(address: Url) {
+    EXPORTS.lock().unwrap().address = Some(address.to_string());
+}
+
+/// Connects directly to the owners of objects received from other nodes using `client`, rather
+/// than calling them through the node they were received from.
+pub fn route(client: Client) {
+    let mut routes = ROUTES.lock().unwrap();
+    routes.client = Some(client);
+    routes.vats.clear();
+}
+
+/// Returns whether the object referred to by `reference` is exported by this node.
+#[doc(hidden)]
+pub fn exported(reference: &Reference) -> bool {
+    get(&reference.token).is_some()
+}
+
+/// Exports an object shared by `share`, returning a reference to it and a guard that keeps the
+/// export alive, or `None` if this node does not advertise an address.
+#[doc(hidden)]
+pub fn export<K: Kind>(
+    share: impl Fn(Arc<dyn Any + Sync + Send>, Reference) -> K + Sync + Send + 'static,
+) -> Future<Option<(Reference, Arc<dyn Any + Sync + Send>)>> {
+    let address = EXPORTS.lock().unwrap().address.clone();
+    Box::pin(async move {
+        let address = address?;
+        let bytes = <dyn Rng>::new().ok()?.bytes(32).await.ok()?;
+        let mut token = [0u8; 32];
+        token.copy_from_slice(&bytes);
+        let reference = Reference { address, token };
+        let share = Arc::new(share);
+        let export = Arc::new(Export {
+            token,
+            local: {
+                let share = share.clone();
+                let reference = reference.clone();
+                Box::new(move |guard| Box::new(share(guard, reference.clone())))
+            },
+            acquire: {
+                let reference = reference.clone();
+                Box::new(move |guard| {
+                    let item = share(guard, reference.clone());
+                    Box::pin(async move {
+                        let (sink, stream) =
+                            item.on_to::<IdChannel>().await.encode::<Cbor>().split();
+                        Ok(SinkStream::new(
+                            sink.sink_map_err(Error::from)
+                                .with(|frame: Frame| ok(frame.0)),
+                            stream.map(Frame),
+                        ))
+                    })
+                })
+            },
+        });
+        EXPORTS
+            .lock()
+            .unwrap()
+            .objects
+            .insert(token, Arc::downgrade(&export));
+        Some((reference, export as Arc<dyn Any + Sync + Send>))
+    })
+}
+
+/// Acquires the object referred to by `reference` from its owner.
+#[doc(hidden)]
+pub fn resolve<K: Kind>(reference: Reference) -> Fallible<K, HandoffError> {
+    if let Some(export) = get(&reference.token) {
+        let item = (export.local)(export.clone() as Arc<dyn Any + Sync + Send>);
+        return Box::pin(async move {
+            Box::<dyn Any>::downcast(item)
+                .map(|item| *item)
+                .map_err(|_| HandoffError::Unexported)
+        });
+    }
+    let vat = vat(reference.address.clone());
+    Box::pin(async move {
+        let vat = vat.await?;
+        match vat.0.acquire(reference.clone()).await {
+            Ok(channel) => channel
+                .map(|frame| frame.0)
+                .with(|frame| ok(Frame(frame)))
+                .sink_map_err(HandoffError::Construct)
+                .decode::<IdChannel, Cbor>()
+                .await
+                .map_err(|e: K::ConstructError| HandoffError::Construct(e.into())),
+            Err(e) => {
+                if let HandoffError::Transport(_) = e {
+                    ROUTES.lock().unwrap().vats.remove(&reference.address);
+                }
+                Err(e)
+            }
+        }
+    })
+}
+
+fn vat(address: String) -> Fallible<Arc<Vat>, HandoffError> {
+    let mut routes = ROUTES.lock().unwrap();
+    if let Some(vat) = routes.vats.get(&address) {
+        let vat = vat.clone();
+        return Box::pin(async move { Ok(vat) });
+    }
+    let connection = match (routes.client.as_mut(), address.parse()) {
+        (Some(client), Ok(url)) => client.connect::<Vat, IdChannel, Cbor>(url),
+        _ => return Box::pin(async { Err(HandoffError::Unroutable) }),
+    };
+    Box::pin(async move {
+        let vat = Arc::new(
+            connection
+                .await
+                .map_err(|e| HandoffError::Connect(e.into()))?,
+        );
+        ROUTES.lock().unwrap().vats.insert(address, vat.clone());
+        Ok(vat)
+    })
+}
+
+#[object]
+trait VatInner {
+    fn acquire(&self, reference: Reference) -> Fallible<Connection, HandoffError>;
+}
+
+/// Serves the objects exported by this node to the nodes holding references to them.
+#[derive(Kind)]
+pub struct Vat(Box<dyn VatInner>);
+
+struct Exported;
+
+impl VatInner for Exported {
+    fn acquire(&self, reference: Reference) -> Fallible<Connection, HandoffError> {
+        match get(&reference.token) {
+            Some(export) => (export.acquire)(export.clone() as Arc<dyn Any + Sync + Send>),
+            None => Box::pin(async { Err(HandoffError::Unexported) }),
+        }
+    }
+}
+
+impl Vat {
+    /// Returns the `Vat` of this node, to be served at the address passed to `advertise`.
+    pub fn new() -> Self {
+        Vat(Box::new(Exported))
+    }
+}
+
+impl Default for Vat {
+    fn default() -> Self {
+        Vat::new()
+    }
+}
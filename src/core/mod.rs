@@ -1,14 +1,14 @@
 use alloc::sync::Arc;
 use anyhow::Error;
-#[cfg(any(target_arch = "wasm32", feature = "core"))]
 use core::any::Any;
-use futures::{lock, SinkExt, StreamExt};
+use futures::{lock, SinkExt, StreamExt, TryFutureExt};
 use lazy_static::lazy_static;
 use std::{collections::HashMap, sync::Mutex};
 use thiserror::Error;
 
 use crate::{
-    channel::IdChannel,
+    channel::{IdChannel, Local},
+    core::sturdy::SturdyRef,
     format::{ApplyDecode, ApplyEncode, Cbor},
     kind::{Fallible, Infallible, SinkStream, TransportError},
     object,
@@ -21,7 +21,9 @@ pub use executor::{run, spawn};
 
 pub mod data;
 pub mod hal;
+pub mod handoff;
 pub mod orchestrator;
+pub mod sturdy;
 
 #[doc(hidden)]
 pub type Constructor<T> = Box<dyn FnOnce(Handle) -> Infallible<T> + Send + Sync>;
@@ -38,9 +40,12 @@ pub enum CoreError {
     Unavailable,
     #[error("{0}")]
     Unimplemented(#[source] UnimplementedError),
+    #[error("sturdy reference failed verification")]
+    Unverified,
     #[error("`handle transfer failed: {0}")]
     Construct(#[source] Error),
     #[error("`underlying transport failed: {0}")]
+    #[kind(lost)]
     Transport(#[from] TransportError),
 }
 
@@ -112,6 +117,11 @@ pub fn acquire<K: Kind>() -> Fallible<K, CoreError> {
 #[object]
 trait HandleInner {
     fn acquire(&self, ty: [u8; 32]) -> Fallible<SinkStream<Vec<u8>, Error, Vec<u8>>, CoreError>;
+    fn save(&self, ty: [u8; 32], id: String) -> Fallible<SturdyRef, CoreError>;
+    fn restore(
+        &self,
+        reference: SturdyRef,
+    ) -> Fallible<SinkStream<Vec<u8>, Error, Vec<u8>>, CoreError>;
 }
 
 #[cfg(feature = "core")]
@@ -123,33 +133,66 @@ lazy_static! {
 #[derive(Kind)]
 pub struct Handle(Box<dyn HandleInner>);
 
+fn construct<K: Kind>(
+    channel: Fallible<SinkStream<Vec<u8>, Error, Vec<u8>>, CoreError>,
+) -> Fallible<K, CoreError> {
+    Box::pin(async move {
+        channel
+            .await?
+            .sink_map_err(CoreError::Construct)
+            .decode::<IdChannel, Cbor>()
+            .await
+            .map_err(|e: K::ConstructError| CoreError::Construct(e.into()))
+    })
+}
+
 impl Handle {
     pub fn acquire<K: Kind>(&self) -> Fallible<K, CoreError> {
-        let channel = self.0.acquire(K::USE_KIND_MACRO_TO_GENERATE_THIS_FIELD);
+        construct(self.0.acquire(K::USE_KIND_MACRO_TO_GENERATE_THIS_FIELD))
+    }
+    /// Saves the capability registered under `id` by the provider of this handle as a sturdy
+    /// reference, which can be persisted and passed to `restore` on any later handle to the same
+    /// provider.
+    pub fn save<K: Kind>(&self, id: impl Into<String>) -> Fallible<SturdyRef, CoreError> {
+        self.0
+            .save(K::USE_KIND_MACRO_TO_GENERATE_THIS_FIELD, id.into())
+    }
+    /// Reacquires the capability a sturdy reference was saved from.
+    pub fn restore<K: Kind>(&self, reference: &SturdyRef) -> Fallible<K, CoreError> {
+        if reference.ty() != K::USE_KIND_MACRO_TO_GENERATE_THIS_FIELD {
+            return Box::pin(async { Err(CoreError::Unavailable) });
+        }
+        construct(self.0.restore(reference.clone()))
+    }
+}
+
+type Capability =
+    Box<dyn Fn() -> Fallible<SinkStream<Vec<u8>, Error, Vec<u8>>, CoreError> + Sync + Send>;
+
+fn capability<K: Kind>(item: impl Fn() -> K + Sync + Send + 'static) -> Capability {
+    let item = Arc::new(lock::Mutex::new(item));
+    Box::new(move || {
+        let item = item.clone();
         Box::pin(async move {
-            channel
-                .await?
-                .sink_map_err(CoreError::Construct)
-                .decode::<IdChannel, Cbor>()
+            let (sink, stream) = (item.lock().await)()
+                .on_to::<IdChannel>()
                 .await
-                .map_err(|e: K::ConstructError| CoreError::Construct(e.into()))
+                .encode::<Cbor>()
+                .split();
+            Ok(SinkStream::new(sink.sink_map_err(Error::from), stream))
         })
-    }
+    })
 }
 
+type LocalCapability = Box<dyn Fn() -> Box<dyn Any + Sync + Send> + Sync + Send>;
+
+type SturdyCapabilities = HashMap<String, ([u8; 32], Capability)>;
+
 pub struct Core {
-    capabilities: Arc<
-        Mutex<
-            HashMap<
-                [u8; 32],
-                Box<
-                    dyn Fn() -> Fallible<SinkStream<Vec<u8>, Error, Vec<u8>>, CoreError>
-                        + Sync
-                        + Send,
-                >,
-            >,
-        >,
-    >,
+    capabilities: Arc<Mutex<HashMap<[u8; 32], Capability>>>,
+    local: Arc<Mutex<HashMap<[u8; 32], LocalCapability>>>,
+    sturdy: Arc<Mutex<SturdyCapabilities>>,
+    secret: Option<Arc<Vec<u8>>>,
 }
 
 impl HandleInner for Core {
@@ -160,6 +203,33 @@ impl HandleInner for Core {
             Box::pin(async move { Err(CoreError::Unavailable) })
         }
     }
+    fn save(&self, ty: [u8; 32], id: String) -> Fallible<SturdyRef, CoreError> {
+        let registered = self.sturdy.lock().unwrap().get(&id).map(|entry| entry.0) == Some(ty);
+        let secret = self.secret.clone();
+        Box::pin(async move {
+            match secret {
+                Some(secret) if registered => SturdyRef::issue(&secret, id, ty).await,
+                _ => Err(CoreError::Unavailable),
+            }
+        })
+    }
+    fn restore(
+        &self,
+        reference: SturdyRef,
+    ) -> Fallible<SinkStream<Vec<u8>, Error, Vec<u8>>, CoreError> {
+        let secret = self.secret.clone();
+        let sturdy = self.sturdy.clone();
+        Box::pin(async move {
+            reference
+                .verify(&secret.ok_or(CoreError::Unavailable)?)
+                .await?;
+            let channel = match sturdy.lock().unwrap().get(reference.id()) {
+                Some((ty, capability)) if *ty == reference.ty() => capability(),
+                _ => return Err(CoreError::Unavailable),
+            };
+            channel.await
+        })
+    }
 }
 
 pub fn register<K: Kind>(item: impl Fn() -> K + Sync + Send + 'static) {
@@ -183,23 +253,60 @@ impl Core {
     pub fn new() -> Self {
         Core {
             capabilities: Arc::new(Mutex::new(HashMap::new())),
+            local: Arc::new(Mutex::new(HashMap::new())),
+            sturdy: Arc::new(Mutex::new(HashMap::new())),
+            secret: None,
+        }
+    }
+    /// Creates a `Core` that issues sturdy references keyed by `secret`. References remain valid
+    /// across restarts of the provider for as long as it is recreated with the same secret.
+    pub fn with_secret(secret: Vec<u8>) -> Self {
+        Core {
+            secret: Some(Arc::new(secret)),
+            ..Core::new()
         }
     }
     pub fn register<K: Kind>(&mut self, item: impl Fn() -> K + Sync + Send + 'static) {
-        let item = Arc::new(lock::Mutex::new(item));
+        let item = Arc::new(item);
+        let local = item.clone();
+        self.local.lock().unwrap().insert(
+            K::USE_KIND_MACRO_TO_GENERATE_THIS_FIELD,
+            Box::new(move || Box::new(local())),
+        );
         self.capabilities.lock().unwrap().insert(
             K::USE_KIND_MACRO_TO_GENERATE_THIS_FIELD,
-            Box::new(move || {
-                let item = item.clone();
-                Box::pin(async move {
-                    let (sink, stream) = (item.lock().await)()
-                        .on_to::<IdChannel>()
-                        .await
-                        .encode::<Cbor>()
-                        .split();
-                    Ok(SinkStream::new(sink.sink_map_err(Error::from), stream))
-                })
-            }),
+            capability(move || item()),
+        );
+    }
+    /// Acquires a capability registered with this `Core` from within the same process. The
+    /// capability is passed through a `Local` channel, so it behaves as it would for a remote
+    /// consumer without being encoded.
+    pub fn acquire_local<K: Kind>(&self) -> Fallible<K, CoreError> {
+        let item = self
+            .local
+            .lock()
+            .unwrap()
+            .get(&K::USE_KIND_MACRO_TO_GENERATE_THIS_FIELD)
+            .and_then(|item| Box::<dyn Any>::downcast::<K>((item)()).ok());
+        let item = match item {
+            Some(item) => *item,
+            None => return Box::pin(async { Err(CoreError::Unavailable) }),
+        };
+        Box::pin(
+            Local::transfer(item).map_err(|e: K::ConstructError| CoreError::Construct(e.into())),
+        )
+    }
+    /// Registers a capability under `id` so that it can be saved as a sturdy reference. A
+    /// restarted provider must register it under the same identifier to honour references saved
+    /// before the restart.
+    pub fn register_sturdy<K: Kind>(
+        &mut self,
+        id: impl Into<String>,
+        item: impl Fn() -> K + Sync + Send + 'static,
+    ) {
+        self.sturdy.lock().unwrap().insert(
+            id.into(),
+            (K::USE_KIND_MACRO_TO_GENERATE_THIS_FIELD, capability(item)),
         );
     }
     pub fn into_handle(self) -> Handle {
@@ -211,6 +318,9 @@ impl Share for Core {
     fn share(&self) -> Self {
         Core {
             capabilities: self.capabilities.clone(),
+            local: self.local.clone(),
+            sturdy: self.sturdy.clone(),
+            secret: self.secret.clone(),
         }
     }
 }
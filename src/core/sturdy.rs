@@ -0,0 +1,84 @@
+//! Sturdy references to capabilities registered with a `Core`.
+//!
+//! A sturdy reference names a capability by an identifier chosen by its provider rather than by
+//! a live fork, so it can be persisted and used to reacquire the capability after either end has
+//! restarted. References are authenticated with HMAC-SHA256 keyed by the provider's secret, which
+//! the provider must keep across restarts for the references it has issued to remain valid.
+
+use crate::{
+    core::{data::Checksum, hal::crypto::Hasher, CoreError},
+    kind::using,
+    Kind,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// A persistable reference to a capability registered with `Core::register_sturdy`.
+#[derive(Serialize, Deserialize, Kind, Debug, Clone, PartialEq, Eq, Hash)]
+#[kind(using::Serde)]
+pub struct SturdyRef {
+    id: String,
+    ty: [u8; 32],
+    mac: Checksum,
+}
+
+/// The block size of SHA-256, to which HMAC pads its key.
+const BLOCK: usize = 64;
+
+/// Computes HMAC-SHA256 of `message` under `key`, as the platform hasher provides no keyed hash.
+async fn hmac(key: &[u8], message: Vec<u8>) -> Result<Checksum, CoreError> {
+    let hasher = <dyn Hasher>::new().map_err(CoreError::Unimplemented)?;
+    let mut block = [0u8; BLOCK];
+    if key.len() > BLOCK {
+        let key = hasher
+            .hash(key.to_vec())
+            .await
+            .map_err(CoreError::Transport)?;
+        block[..key.0.len()].copy_from_slice(&key.0);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<_>>();
+    let mut inner = pad(0x36);
+    inner.extend(message);
+    let inner = hasher.hash(inner).await.map_err(CoreError::Transport)?;
+    let mut outer = pad(0x5c);
+    outer.extend_from_slice(&inner.0);
+    hasher.hash(outer).await.map_err(CoreError::Transport)
+}
+
+async fn mac(secret: &[u8], id: &str, ty: [u8; 32]) -> Result<Checksum, CoreError> {
+    hmac(secret, serde_cbor::to_vec(&(id, ty)).unwrap()).await
+}
+
+impl SturdyRef {
+    /// Returns the identifier the provider registered the capability under.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub(crate) fn ty(&self) -> [u8; 32] {
+        self.ty
+    }
+
+    pub(crate) async fn issue(secret: &[u8], id: String, ty: [u8; 32]) -> Result<Self, CoreError> {
+        let mac = mac(secret, &id, ty).await?;
+        Ok(SturdyRef { id, ty, mac })
+    }
+
+    pub(crate) async fn verify(&self, secret: &[u8]) -> Result<(), CoreError> {
+        let expected = mac(secret, &self.id, self.ty).await?;
+        // Compared in constant time so that a forged reference reveals nothing about the secret.
+        if expected
+            .0
+            .iter()
+            .zip(self.mac.0.iter())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+        {
+            Ok(())
+        } else {
+            Err(CoreError::Unverified)
+        }
+    }
+}
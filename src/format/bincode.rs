@@ -21,8 +21,12 @@ impl Format for Bincode {
     type Representation = Vec<u8>;
     type Error = serde_bincode::Error;
 
-    fn serialize<T: Serialize>(item: T) -> Self::Representation {
-        serde_bincode::serialize(&item).unwrap()
+    fn name() -> String {
+        "bincode".to_owned()
+    }
+
+    fn serialize<T: Serialize>(item: T) -> Result<Self::Representation, Self::Error> {
+        serde_bincode::serialize(&item)
     }
 
     fn deserialize<'de, T: DeserializeSeed<'de>>(
@@ -0,0 +1,397 @@
+//! Capture and replay of encoded connections.
+//!
+//! A `Capture` wraps the encoded side of a connection, whether the input to `decode` or the
+//! output of `encode`, and records every frame that passes through it in either direction along
+//! with the time at which it did so. A `Replay` reads such a capture back and produces a
+//! connection that yields the captured frames again, so that the session can be reproduced
+//! offline by decoding from it.
+//!
+//! Frames are recorded relative to the wrapped connection: those it yielded as a stream and
+//! those sent into it as a sink. The frames a decoding side needs are those yielded by its own
+//! connection or, equivalently, by the output of the encoding side, so a capture taken on either
+//! end can be replayed into `decode`.
+//!
+//! Frames are written as byte strings, whatever the representation of their format.
+
+use super::{Binary, Format};
+
+use crate::kind::{deadline::now, SinkStream};
+
+use alloc::sync::Arc;
+use core::{
+    convert::Infallible,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use futures::{
+    channel::{
+        mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    future::pending,
+    sink::drain,
+    stream::{iter, unfold},
+    Sink as ISink, Stream as IStream, StreamExt,
+};
+use futures_timer::Delay;
+use serde::{Deserialize, Serialize};
+use serde_bytes::{ByteBuf, Bytes};
+use std::{
+    io::{Read, Write},
+    sync::Mutex,
+};
+use thiserror::Error;
+
+/// The version of the capture file layout written by `Capture`.
+const CAPTURE_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    format: String,
+}
+
+/// The side of a captured connection that a frame passed through.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The frame was yielded by the connection.
+    Stream,
+    /// The frame was sent into the connection.
+    Sink,
+}
+
+/// Something that happened on one side of a captured connection.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Event<R> {
+    Frame(R),
+    /// Nothing further passed in this direction.
+    End,
+}
+
+/// An entry in a capture, stamped with the time elapsed since the capture began.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Record<R> {
+    pub direction: Direction,
+    pub elapsed: Duration,
+    pub event: Event<R>,
+}
+
+#[derive(Error, Debug)]
+pub enum CaptureError {
+    #[error("failed to write capture: {0}")]
+    Write(#[source] serde_cbor::Error),
+    #[error("failed to read capture: {0}")]
+    Read(#[from] std::io::Error),
+    #[error("capture is empty")]
+    Empty,
+    #[error("malformed capture: {0}")]
+    Malformed(#[source] serde_cbor::Error),
+    #[error("capture has version {0}, expected {}", CAPTURE_VERSION)]
+    Version(u32),
+    #[error("capture is of format `{found}`, expected `{expected}`")]
+    Format { found: String, expected: String },
+    #[error("captured frame is not a valid representation of its format")]
+    Representation,
+}
+
+/// A clock giving the time elapsed since the capture began.
+type Clock = Box<dyn Fn() -> Duration + Sync + Send>;
+
+/// Returns a clock of the platform starting from now. Vessels have none, so the clock of a
+/// capture taken within one never advances unless one is given with `Capture::with_clock`.
+fn clock() -> Clock {
+    #[cfg(not(target_arch = "wasm32"))]
+    let clock: Clock = {
+        let started = std::time::Instant::now();
+        Box::new(move || started.elapsed())
+    };
+    #[cfg(all(target_arch = "wasm32", feature = "core"))]
+    let clock: Clock = {
+        let started = js_sys::Date::now();
+        Box::new(move || Duration::from_secs_f64((js_sys::Date::now() - started).max(0.) / 1000.))
+    };
+    #[cfg(all(target_arch = "wasm32", not(feature = "core")))]
+    let clock: Clock = Box::new(Duration::default);
+    clock
+}
+
+/// What is handed to the writer of a capture.
+enum Writing {
+    Record(Vec<u8>),
+    /// Flush the writer, then signal that every record handed over before has been written.
+    Flush(oneshot::Sender<()>),
+}
+
+/// Stamps records and hands them to a writer of their own, so that the connection is never
+/// held up by writing them.
+struct Recorder {
+    records: UnboundedSender<Writing>,
+    clock: Clock,
+}
+
+impl Recorder {
+    fn new(writer: Box<dyn Write + Send>, clock: Clock) -> Self {
+        let (records, receiver) = unbounded();
+        let writing = write(writer, receiver);
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(move || futures::executor::block_on(writing));
+        #[cfg(target_arch = "wasm32")]
+        crate::core::spawn(writing);
+        Recorder { records, clock }
+    }
+
+    fn record<R: AsRef<[u8]>>(&self, direction: Direction, event: Event<&R>) {
+        let record = Record {
+            direction,
+            elapsed: (self.clock)(),
+            event: match event {
+                Event::Frame(frame) => Event::Frame(Bytes::new(frame.as_ref())),
+                Event::End => Event::End,
+            },
+        };
+        // The connection is never disturbed by its capture, which simply stops should writing
+        // to it fail.
+        let _ = self
+            .records
+            .unbounded_send(Writing::Record(serde_cbor::to_vec(&record).unwrap()));
+    }
+}
+
+/// Writes records to `writer` as they are recorded, until writing fails.
+async fn write(writer: Box<dyn Write + Send>, mut records: UnboundedReceiver<Writing>) {
+    // The writer is only ever used here, it is locked so that writing can be spawned as a task
+    // where there are no threads.
+    let writer = Mutex::new(writer);
+    while let Some(item) = records.next().await {
+        let mut writer = writer.lock().unwrap();
+        match item {
+            Writing::Record(record) => {
+                if writer.write_all(&record).is_err() {
+                    return;
+                }
+            }
+            Writing::Flush(flushed) => {
+                if writer.flush().is_err() {
+                    return;
+                }
+                let _ = flushed.send(());
+            }
+        }
+    }
+}
+
+/// Waits upon the writer of a `Capture`, see `Capture::flusher`.
+#[derive(Clone)]
+pub struct Flusher(UnboundedSender<Writing>);
+
+impl Flusher {
+    /// Resolves once every record made before it was called has been written and the writer
+    /// flushed, or once writing has failed.
+    pub async fn flush(&self) {
+        let (flushed, done) = oneshot::channel();
+        if self.0.unbounded_send(Writing::Flush(flushed)).is_ok() {
+            let _ = done.await;
+        }
+    }
+}
+
+/// A connection whose frames are recorded to a capture as they pass through it.
+///
+/// Records are written as they occur by a writer of their own, on a thread of its own natively,
+/// so that a slow `writer` never holds up the connection. Records yet to be written are held in
+/// memory until it catches up, and may be waited upon with a `Flusher`. `writer` should be
+/// buffered if frames are frequent and unbuffered if the capture must survive the process
+/// exiting abruptly. It is dropped once every record has been written after the `Capture` and
+/// any `Flusher` of it are dropped.
+pub struct Capture<C, R> {
+    connection: Pin<Box<C>>,
+    recorder: Arc<Recorder>,
+    representation: PhantomData<fn(R)>,
+}
+
+impl<R: AsRef<[u8]>, C: IStream<Item = R> + ISink<R>> Capture<C, R> {
+    /// Wraps `connection`, which carries frames of format `F`, and begins a capture of it to
+    /// `writer`.
+    pub fn new<F: Format<Representation = R>>(
+        connection: C,
+        writer: impl Write + Send + 'static,
+    ) -> Result<Self, CaptureError> {
+        Capture::with_clock::<F>(connection, writer, clock())
+    }
+
+    /// As `new`, stamping records with the time given by `clock`, which should start from zero
+    /// and never go back. Within a vessel there is no clock, so records are all stamped zero
+    /// unless one is given here.
+    pub fn with_clock<F: Format<Representation = R>>(
+        connection: C,
+        mut writer: impl Write + Send + 'static,
+        clock: impl Fn() -> Duration + Sync + Send + 'static,
+    ) -> Result<Self, CaptureError> {
+        let header = Header {
+            version: CAPTURE_VERSION,
+            format: F::name(),
+        };
+        serde_cbor::to_writer(&mut writer, &header).map_err(CaptureError::Write)?;
+        Ok(Capture {
+            connection: Box::pin(connection),
+            recorder: Arc::new(Recorder::new(Box::new(writer), Box::new(clock))),
+            representation: PhantomData,
+        })
+    }
+
+    /// Returns a `Flusher` for the writer of this capture, which remains usable after the
+    /// capture is handed off.
+    pub fn flusher(&self) -> Flusher {
+        Flusher(self.recorder.records.clone())
+    }
+}
+
+impl<R: AsRef<[u8]>, C: IStream<Item = R>> IStream for Capture<C, R> {
+    type Item = R;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let item = self.connection.as_mut().poll_next(cx);
+        match &item {
+            Poll::Ready(Some(frame)) => {
+                self.recorder.record(Direction::Stream, Event::Frame(frame))
+            }
+            Poll::Ready(None) => self.recorder.record::<R>(Direction::Stream, Event::End),
+            Poll::Pending => {}
+        }
+        item
+    }
+}
+
+impl<R: AsRef<[u8]>, C: ISink<R>> ISink<R> for Capture<C, R> {
+    type Error = C::Error;
+
+    fn start_send(mut self: Pin<&mut Self>, item: R) -> Result<(), Self::Error> {
+        self.recorder.record(Direction::Sink, Event::Frame(&item));
+        self.connection.as_mut().start_send(item)
+    }
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.connection.as_mut().poll_ready(cx)
+    }
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.connection.as_mut().poll_flush(cx)
+    }
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        let closed = self.connection.as_mut().poll_close(cx);
+        if let Poll::Ready(Ok(())) = closed {
+            self.recorder.record::<R>(Direction::Sink, Event::End);
+        }
+        closed
+    }
+}
+
+/// A capture read back for replay.
+pub struct Replay<R> {
+    records: Vec<Record<R>>,
+}
+
+impl<R: Binary + Sync + Send + 'static> Replay<R> {
+    /// Reads a capture of a connection carrying frames of format `F`. A capture that was cut
+    /// short while a record was being written is read up to that record.
+    pub fn read<F: Format<Representation = R>>(
+        mut reader: impl Read,
+    ) -> Result<Self, CaptureError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let mut headers = serde_cbor::Deserializer::from_slice(&bytes).into_iter::<Header>();
+        let header = headers
+            .next()
+            .ok_or(CaptureError::Empty)?
+            .map_err(CaptureError::Malformed)?;
+        if header.version != CAPTURE_VERSION {
+            return Err(CaptureError::Version(header.version));
+        }
+        if header.format != F::name() {
+            return Err(CaptureError::Format {
+                found: header.format,
+                expected: F::name(),
+            });
+        }
+        let mut records = vec![];
+        for record in serde_cbor::Deserializer::from_slice(&bytes[headers.byte_offset()..])
+            .into_iter::<Record<ByteBuf>>()
+        {
+            match record {
+                Ok(record) => records.push(Record {
+                    direction: record.direction,
+                    elapsed: record.elapsed,
+                    event: match record.event {
+                        Event::Frame(frame) => Event::Frame(
+                            R::from_bytes(frame.into_vec())
+                                .map_err(|_| CaptureError::Representation)?,
+                        ),
+                        Event::End => Event::End,
+                    },
+                }),
+                Err(error) if error.is_eof() => break,
+                Err(error) => return Err(CaptureError::Malformed(error)),
+            }
+        }
+        Ok(Replay { records })
+    }
+
+    /// Returns every record in the capture in the order in which they occurred.
+    pub fn records(&self) -> &[Record<R>] {
+        &self.records
+    }
+
+    /// Returns a connection that yields the captured frames as soon as they are read, for
+    /// decoding with `ApplyDecode::decode`. Frames sent into it are discarded.
+    ///
+    /// If the captured connection ended, the replayed one ends after its last frame, otherwise
+    /// it remains open as the captured connection was when the capture stopped. Replies to
+    /// remote calls are yielded before the calls can be made again and are therefore lost, so
+    /// sessions involving calls should be reproduced with `paced_connection`.
+    pub fn connection(self) -> SinkStream<R, Infallible, R> {
+        let (frames, ended) = self.frames();
+        SinkStream::new(
+            drain(),
+            iter(frames.into_iter().map(|(_, frame)| frame)).chain(tail(ended)),
+        )
+    }
+
+    /// As `connection`, yielding each frame only once as much time has elapsed since the
+    /// connection was created as had elapsed in the capture.
+    pub fn paced_connection(self) -> SinkStream<R, Infallible, R> {
+        let (frames, ended) = self.frames();
+        let started = now();
+        let frames = unfold(frames.into_iter(), move |mut frames| async move {
+            let (elapsed, frame) = frames.next()?;
+            Delay::new((started + elapsed).checked_sub(now()).unwrap_or_default()).await;
+            Some((frame, frames))
+        });
+        SinkStream::new(drain(), frames.chain(tail(ended)))
+    }
+
+    fn frames(self) -> (Vec<(Duration, R)>, bool) {
+        let mut ended = false;
+        let frames = self
+            .records
+            .into_iter()
+            .filter(|record| record.direction == Direction::Stream)
+            .filter_map(|record| match record.event {
+                Event::Frame(frame) => Some((record.elapsed, frame)),
+                Event::End => {
+                    ended = true;
+                    None
+                }
+            })
+            .collect();
+        (frames, ended)
+    }
+}
+
+fn tail<R: Send + 'static>(ended: bool) -> impl IStream<Item = R> + Sync + Send {
+    unfold(ended, |ended| async move {
+        if !ended {
+            pending::<()>().await;
+        }
+        None
+    })
+}
@@ -20,8 +20,12 @@ impl Format for Cbor {
     type Representation = Vec<u8>;
     type Error = serde_cbor::Error;
 
-    fn serialize<T: Serialize>(item: T) -> Self::Representation {
-        serde_cbor::to_vec(&item).unwrap()
+    fn name() -> String {
+        "cbor".to_owned()
+    }
+
+    fn serialize<T: Serialize>(item: T) -> Result<Self::Representation, Self::Error> {
+        serde_cbor::to_vec(&item)
     }
 
     fn deserialize<'de, T: DeserializeSeed<'de>>(
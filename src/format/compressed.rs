@@ -0,0 +1,137 @@
+use super::Format;
+
+use serde::{de::DeserializeSeed, Serialize};
+
+use crate::{kind::Fallible, ErrorBound};
+
+use core::marker::PhantomData;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression as Level};
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+/// Marks a frame that is carried as produced by the wrapped format.
+const UNCOMPRESSED: u8 = 0;
+/// Marks a frame that has been compressed with DEFLATE.
+const DEFLATE: u8 = 1;
+
+/// The settings with which a `Compressed` format compresses frames.
+///
+/// Only frames of at least `THRESHOLD` bytes are compressed, as small frames gain little and
+/// cost a round of compression on either end. `LEVEL` ranges from 0, which stores the frame
+/// without compressing it, to 9, which compresses it as far as possible at the greatest cost.
+/// Frames received that decompress to more than `MAX_FRAME` bytes are rejected, so that a small
+/// frame from a peer cannot exhaust memory by inflating without bound.
+pub trait Compression: Sync + Send + 'static {
+    const LEVEL: u32;
+    const THRESHOLD: usize = 1024;
+    const MAX_FRAME: usize = 16 * 1024 * 1024;
+}
+
+/// Compresses frames quickly at the expense of their size.
+pub struct Fast;
+
+impl Compression for Fast {
+    const LEVEL: u32 = 1;
+}
+
+/// Strikes a balance between the speed of compression and the size of frames.
+pub struct Balanced;
+
+impl Compression for Balanced {
+    const LEVEL: u32 = 6;
+}
+
+/// Compresses frames as far as possible at the expense of speed.
+pub struct Best;
+
+impl Compression for Best {
+    const LEVEL: u32 = 9;
+}
+
+#[derive(Error, Debug)]
+pub enum CompressedError<E: ErrorBound> {
+    #[error("frame is empty")]
+    Empty,
+    #[error("frame has unknown compression flag {0}")]
+    Flag(u8),
+    #[error("failed to decompress frame: {0}")]
+    Decompress(#[source] io::Error),
+    #[error("frame decompresses to more than {0} bytes")]
+    TooLarge(usize),
+    #[error("{0}")]
+    Format(#[source] E),
+}
+
+/// A format wrapping another binary format and compressing the frames it produces.
+///
+/// Each frame is prefixed with a flag byte indicating whether what follows was compressed, so
+/// that frames below the threshold of `C`, and those that compression would not make any
+/// smaller, are carried as the wrapped format produced them. Compression uses DEFLATE as
+/// provided by `flate2`. As the level and threshold only concern the compressing end, both ends
+/// need only agree on the wrapped format.
+///
+/// For this format to be used the `compression` feature must be enabled.
+pub struct Compressed<F, C: Compression = Balanced>(PhantomData<(F, C)>);
+
+impl<F: Format<Representation = Vec<u8>>, C: Compression> Format for Compressed<F, C> {
+    type Representation = Vec<u8>;
+    type Error = CompressedError<F::Error>;
+
+    fn name() -> String {
+        format!("{}+deflate", F::name())
+    }
+
+    fn serialize<T: Serialize>(item: T) -> Result<Self::Representation, Self::Error> {
+        let frame = F::serialize(item).map_err(CompressedError::Format)?;
+        if frame.len() >= C::THRESHOLD {
+            let mut encoder = DeflateEncoder::new(vec![DEFLATE], Level::new(C::LEVEL));
+            let compressed = encoder.write_all(&frame).and_then(|_| encoder.finish());
+            if let Ok(compressed) = compressed {
+                if compressed.len() < frame.len() + 1 {
+                    return Ok(compressed);
+                }
+            }
+        }
+        let mut item = Vec::with_capacity(frame.len() + 1);
+        item.push(UNCOMPRESSED);
+        item.extend(frame);
+        Ok(item)
+    }
+
+    fn deserialize<'de, T: DeserializeSeed<'de>>(
+        item: Self::Representation,
+        context: T,
+    ) -> Fallible<T::Value, (Self::Error, Self::Representation)>
+    where
+        T: Sync + Send + 'static,
+    {
+        Box::pin(async move {
+            match decompress::<_, C>(&item) {
+                Ok(frame) => F::deserialize(frame, context)
+                    .await
+                    .map_err(|(e, _)| (CompressedError::Format(e), item)),
+                Err(e) => Err((e, item)),
+            }
+        })
+    }
+}
+
+fn decompress<E: ErrorBound, C: Compression>(item: &[u8]) -> Result<Vec<u8>, CompressedError<E>> {
+    match item.split_first() {
+        None => Err(CompressedError::Empty),
+        Some((&UNCOMPRESSED, frame)) => Ok(frame.to_vec()),
+        Some((&DEFLATE, frame)) => {
+            let mut decompressed = vec![];
+            // One byte beyond the limit is read to tell a frame over it from one of exactly the limit.
+            DeflateDecoder::new(frame)
+                .take(C::MAX_FRAME as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .map_err(CompressedError::Decompress)?;
+            if decompressed.len() > C::MAX_FRAME {
+                return Err(CompressedError::TooLarge(C::MAX_FRAME));
+            }
+            Ok(decompressed)
+        }
+        Some((&flag, _)) => Err(CompressedError::Flag(flag)),
+    }
+}
@@ -0,0 +1,179 @@
+use super::Layer;
+
+use crate::kind::Stream;
+
+use anyhow::Error;
+use futures::{
+    channel::oneshot,
+    future::ready,
+    stream::{empty, once},
+    FutureExt, StreamExt,
+};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    hkdf::{Salt, HKDF_SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+use thiserror::Error;
+
+/// The length of the salt each end of a connection chooses for it.
+const SALT_LEN: usize = 16;
+
+/// Derives a key for `Encrypted` from a shared secret using HKDF-SHA256. The salt need not be
+/// secret, but distinct uses of the same secret should use distinct salts.
+pub fn derive_key(secret: &[u8], salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    Salt::new(HKDF_SHA256, salt)
+        .extract(secret)
+        .expand(&[b"vessels encrypted format"], &CHACHA20_POLY1305)
+        .unwrap()
+        .fill(&mut key)
+        .unwrap();
+    key
+}
+
+#[derive(Error, Debug)]
+pub enum EncryptedError {
+    #[error("connection did not begin with a salt")]
+    Salt,
+    #[error("connection began with the salt of the end receiving it")]
+    Reflected,
+    #[error("frame {0} failed authentication")]
+    Authentication(u64),
+}
+
+/// A `Layer` sealing every frame of a connection in a binary format with ChaCha20-Poly1305,
+/// providing confidentiality and integrity where a connection cannot be secured by TLS.
+///
+/// Both ends of a connection must use the same key, whether it is shared ahead of time or
+/// obtained from a shared secret with `derive_key`. Each end chooses a random salt for the
+/// connection and sends it before anything else, and sends no frame until it has the salt of
+/// the other end. The key for each direction is then derived from the key given and both
+/// salts, so that it differs between connections, between the directions of each, and from
+/// that of any earlier connection being replayed, as long as either end is honest. Frames are
+/// sealed under a nonce counting those sent before them, which is never sent, so that frames
+/// replayed, reordered, dropped, carried into another connection or reflected back to their
+/// sender fail authentication and end the connection.
+///
+/// For this layer to be used the `core` feature must be enabled. It is not available on the
+/// web.
+pub struct Encrypted {
+    key: [u8; 32],
+}
+
+impl Encrypted {
+    pub fn new(key: [u8; 32]) -> Self {
+        Encrypted { key }
+    }
+}
+
+/// The key for the direction from the end that chose `from` to the one that chose `to`.
+fn derive(key: &[u8; 32], from: &[u8; SALT_LEN], to: &[u8; SALT_LEN]) -> Sequenced {
+    let salt = [&from[..], &to[..]].concat();
+    Sequenced {
+        key: LessSafeKey::new(
+            UnboundKey::new(&CHACHA20_POLY1305, &derive_key(key, &salt)).unwrap(),
+        ),
+        sequence: 0,
+    }
+}
+
+/// A key for one direction of a connection, along with the number of frames sealed or opened
+/// with it so far.
+struct Sequenced {
+    key: LessSafeKey,
+    sequence: u64,
+}
+
+impl Sequenced {
+    fn nonce(&mut self) -> (u64, Nonce) {
+        let sequence = self.sequence;
+        self.sequence += 1;
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[NONCE_LEN - 8..].copy_from_slice(&sequence.to_be_bytes());
+        (sequence, Nonce::assume_unique_for_key(nonce))
+    }
+
+    fn seal(&mut self, name: &str, mut frame: Vec<u8>) -> Vec<u8> {
+        let (_, nonce) = self.nonce();
+        self.key
+            .seal_in_place_append_tag(nonce, Aad::from(name.as_bytes()), &mut frame)
+            .unwrap();
+        frame
+    }
+
+    fn open(&mut self, name: &str, mut frame: Vec<u8>) -> Result<Vec<u8>, EncryptedError> {
+        let (sequence, nonce) = self.nonce();
+        let len = self
+            .key
+            .open_in_place(nonce, Aad::from(name.as_bytes()), &mut frame)
+            .map_err(|_| EncryptedError::Authentication(sequence))?
+            .len();
+        frame.truncate(len);
+        Ok(frame)
+    }
+}
+
+/// Checks that `peer` is the salt chosen by the other end of a connection for which this end
+/// chose `salt`.
+fn check(salt: &[u8; SALT_LEN], peer: &[u8]) -> Result<[u8; SALT_LEN], EncryptedError> {
+    if peer.len() != SALT_LEN {
+        return Err(EncryptedError::Salt);
+    }
+    if peer == &salt[..] {
+        return Err(EncryptedError::Reflected);
+    }
+    let mut checked = [0u8; SALT_LEN];
+    checked.copy_from_slice(peer);
+    Ok(checked)
+}
+
+impl Layer<Vec<u8>> for Encrypted {
+    fn name(&self, format: String) -> String {
+        format!("{}+chacha20poly1305", format)
+    }
+
+    fn wrap(
+        &self,
+        format: &str,
+        sent: Stream<Vec<u8>>,
+        received: Stream<Vec<u8>>,
+    ) -> (Stream<Vec<u8>>, Stream<Result<Vec<u8>, Error>>) {
+        let mut salt = [0u8; SALT_LEN];
+        SystemRandom::new().fill(&mut salt).unwrap();
+        let key = self.key;
+        let name = self.name(format.to_owned());
+        let (established, peer) = oneshot::channel::<[u8; SALT_LEN]>();
+        let sealed = {
+            let name = name.clone();
+            once(ready(salt.to_vec())).chain(
+                peer.map(move |peer| match peer {
+                    Ok(peer) => {
+                        let mut key = derive(&key, &salt, &peer);
+                        Box::pin(sent.map(move |frame| key.seal(&name, frame))) as Stream<Vec<u8>>
+                    }
+                    // The connection ended before the other end sent its salt.
+                    Err(_) => Box::pin(empty()),
+                })
+                .flatten_stream(),
+            )
+        };
+        let opened = received
+            .into_future()
+            .map(
+                move |(peer, received)| match peer.map(|peer| check(&salt, &peer)) {
+                    None => Box::pin(empty()) as Stream<Result<Vec<u8>, Error>>,
+                    Some(Err(e)) => Box::pin(once(ready(Err(e.into())))),
+                    Some(Ok(peer)) => {
+                        let _ = established.send(peer);
+                        let mut key = derive(&key, &peer, &salt);
+                        Box::pin(
+                            received.map(move |frame| key.open(&name, frame).map_err(Error::from)),
+                        )
+                    }
+                },
+            )
+            .flatten_stream();
+        (Box::pin(sealed), Box::pin(opened))
+    }
+}
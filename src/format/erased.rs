@@ -0,0 +1,264 @@
+//! Dispatch over formats chosen at runtime.
+//!
+//! `Format`s are ordinarily chosen statically, by the type given to `encode` and `decode`. Where
+//! the format is only known once a connection is set up, such as when a server lets each client
+//! choose one, a `Formats` holds the candidates behind an object safe interface. Items are
+//! serialized through `erased_serde` and deserialized with a type-erased seed, and the chosen
+//! format's representation is carried as bytes. A format may be added along with a `Layer`
+//! applied to every connection in it, under the name the layer gives it.
+
+use super::{
+    opened,
+    pending::{Buffered, Failure},
+    Binary, Format, Layer, Plain, UniformStreamSink,
+};
+
+use crate::{
+    channel::{Context, Incoming, Waiter},
+    core::spawn,
+    kind::{Fallible, SinkStream, Stream},
+    ErrorBound,
+};
+
+use alloc::sync::Arc;
+use anyhow::Error;
+use core::{any::Any, marker::PhantomData};
+use futures::{
+    channel::mpsc::{channel, Receiver, SendError},
+    future::ready,
+    Sink as ISink, SinkExt, StreamExt, TryFutureExt,
+};
+use serde::de::{self, DeserializeSeed, Deserializer};
+use std::error::Error as StdError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub(crate) enum DispatchError {
+    #[error("frame is not a valid representation of the format")]
+    Representation,
+    #[error("{0}")]
+    Format(#[source] Box<dyn StdError + Sync + Send>),
+}
+
+/// A `DeserializeSeed` whose value is boxed so that it can be passed through a `Dispatch`.
+pub(crate) trait Seed: Sync + Send {
+    fn deserialize(
+        self: Box<Self>,
+        deserializer: &mut dyn erased_serde::Deserializer<'static>,
+    ) -> Result<Box<dyn Any + Sync + Send>, erased_serde::Error>;
+}
+
+impl<T: DeserializeSeed<'static> + Sync + Send> Seed for T
+where
+    T::Value: Sync + Send + 'static,
+{
+    fn deserialize(
+        self: Box<Self>,
+        deserializer: &mut dyn erased_serde::Deserializer<'static>,
+    ) -> Result<Box<dyn Any + Sync + Send>, erased_serde::Error> {
+        DeserializeSeed::deserialize(*self, deserializer)
+            .map(|value| Box::new(value) as Box<dyn Any + Sync + Send>)
+    }
+}
+
+struct Erased(Box<dyn Seed>);
+
+impl DeserializeSeed<'static> for Erased {
+    type Value = Box<dyn Any + Sync + Send>;
+
+    fn deserialize<D: Deserializer<'static>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        self.0
+            .deserialize(&mut <dyn erased_serde::Deserializer>::erase(deserializer))
+            .map_err(de::Error::custom)
+    }
+}
+
+/// The frames to be sent over a connection, and those received over it, as wrapped by a layer.
+type Wrapped = (Stream<Vec<u8>>, Stream<Result<Vec<u8>, Error>>);
+
+/// The object safe counterpart of `Format`, along with the layer applied to its connections.
+pub(crate) trait Dispatch: Sync + Send {
+    fn name(&self) -> String;
+    fn serialize(&self, item: &dyn erased_serde::Serialize) -> Result<Vec<u8>, DispatchError>;
+    fn deserialize(
+        &self,
+        item: Vec<u8>,
+        seed: Box<dyn Seed>,
+    ) -> Fallible<Box<dyn Any + Sync + Send>, (DispatchError, Vec<u8>)>;
+    fn wrap(&self, sent: Stream<Vec<u8>>, received: Stream<Vec<u8>>) -> Wrapped;
+}
+
+struct Dispatcher<F> {
+    layer: Box<dyn Layer<Vec<u8>>>,
+    format: PhantomData<fn() -> F>,
+}
+
+impl<F: Format + 'static> Dispatch for Dispatcher<F>
+where
+    F::Representation: Binary + Sync + Send,
+{
+    fn name(&self) -> String {
+        self.layer.name(F::name())
+    }
+
+    fn serialize(&self, item: &dyn erased_serde::Serialize) -> Result<Vec<u8>, DispatchError> {
+        F::serialize(item)
+            .map(Binary::into_bytes)
+            .map_err(|e| DispatchError::Format(Box::new(e)))
+    }
+
+    fn deserialize(
+        &self,
+        item: Vec<u8>,
+        seed: Box<dyn Seed>,
+    ) -> Fallible<Box<dyn Any + Sync + Send>, (DispatchError, Vec<u8>)> {
+        let item = match F::Representation::from_bytes(item) {
+            Ok(item) => item,
+            Err(item) => return Box::pin(ready(Err((DispatchError::Representation, item)))),
+        };
+        Box::pin(
+            F::deserialize(item, Erased(seed))
+                .map_err(|(e, item)| (DispatchError::Format(Box::new(e)), item.into_bytes())),
+        )
+    }
+
+    fn wrap(&self, sent: Stream<Vec<u8>>, received: Stream<Vec<u8>>) -> Wrapped {
+        self.layer.wrap(&F::name(), sent, received)
+    }
+}
+
+/// A set of formats among which one is chosen at runtime for each connection.
+///
+/// Formats are identified by their `Format::name` and must have a representation that can be
+/// carried as bytes. The default set holds `Cbor` along with `Json` and `Bincode` where their
+/// features are enabled.
+#[derive(Clone)]
+pub struct Formats(Vec<Arc<dyn Dispatch>>);
+
+impl Formats {
+    /// Returns an empty set of formats.
+    pub fn new() -> Self {
+        Formats(vec![])
+    }
+
+    /// Adds `F` to the set, replacing any format already in it of the same name.
+    pub fn with<F: Format + 'static>(self) -> Self
+    where
+        F::Representation: Binary + Sync + Send,
+    {
+        self.with_layer::<F>(Box::new(Plain))
+    }
+
+    /// Adds `F` carried through `layer` to the set, under the name `layer` gives it, replacing
+    /// any format already in it of that name. Clients connect to it with
+    /// `Client::connect_through` and the same layer.
+    pub fn with_layer<F: Format + 'static>(mut self, layer: Box<dyn Layer<Vec<u8>>>) -> Self
+    where
+        F::Representation: Binary + Sync + Send,
+    {
+        let format = Dispatcher::<F> {
+            layer,
+            format: PhantomData,
+        };
+        let name = format.name();
+        self.0.retain(|format| format.name() != name);
+        self.0.push(Arc::new(format));
+        self
+    }
+
+    /// Returns the names of the formats in the set, in the order they were added.
+    pub fn names(&self) -> Vec<String> {
+        self.0.iter().map(|format| format.name()).collect()
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<Arc<dyn Dispatch>> {
+        self.0.iter().find(|format| format.name() == name).cloned()
+    }
+}
+
+impl Default for Formats {
+    fn default() -> Self {
+        let formats = Formats::new().with::<super::Cbor>();
+        #[cfg(feature = "json")]
+        let formats = formats.with::<super::Json>();
+        #[cfg(feature = "bincode")]
+        let formats = formats.with::<super::Bincode>();
+        formats
+    }
+}
+
+fn deserialize<I: Sync + Send + 'static, S>(
+    format: &Arc<dyn Dispatch>,
+    item: Vec<u8>,
+    seed: S,
+) -> Fallible<Incoming<I>, (DispatchError, Vec<u8>)>
+where
+    S: DeserializeSeed<'static, Value = Incoming<I>> + Sync + Send + 'static,
+{
+    Box::pin(
+        format
+            .deserialize(item, Box::new(seed))
+            // The seed produces exactly this type.
+            .map_ok(|value| *value.downcast::<Incoming<I>>().unwrap()),
+    )
+}
+
+/// Encodes `input` in `format` as `Encode::encode` does for a statically chosen format.
+pub(crate) fn encode<C>(
+    format: Arc<dyn Dispatch>,
+    input: C,
+) -> SinkStream<Vec<u8>, SendError, Vec<u8>>
+where
+    C: UniformStreamSink<<C as Context<'static>>::Item> + Context<'static> + Sync + Send + 'static,
+    <C as ISink<<C as Context<'static>>::Item>>::Error: ErrorBound,
+{
+    let ctx = input.context();
+    let limits = input.limits();
+    let (sink, stream) = input.split();
+    let (sender, receiver): (_, Receiver<Vec<u8>>) = channel(limits.connection);
+    let waiter = ctx.clone();
+    let failed = ctx.clone();
+    let unserializable = ctx.clone();
+    // An item that fails to serialize ends the connection, failing every fork on it with the
+    // error.
+    let (sent, received) = format.wrap(
+        Box::pin(stream.scan(unserializable, {
+            let format = format.clone();
+            move |unserializable, item| {
+                ready(match format.serialize(&item) {
+                    Ok(item) => Some(item),
+                    Err(e) => {
+                        unserializable.fail(e.into());
+                        None
+                    }
+                })
+            }
+        })),
+        Box::pin(receiver),
+    );
+    let receiver: Stream<Result<<C as Context<'static>>::Item, Error>> = Box::pin(
+        Buffered::new(
+            opened(received, ctx.clone()),
+            move |item| deserialize(&format, item, ctx.clone()),
+            waiter,
+            limits,
+        )
+        .map(|item| {
+            item.map_err(|failure| match failure {
+                Failure::Format(e) => e.into(),
+                Failure::Pending(e) => e.into(),
+            })
+        }),
+    );
+    spawn(async move {
+        let mut sink = Box::pin(sink.sink_map_err(Error::from));
+        if let Err(cause) = receiver.forward(&mut sink).await {
+            failed.fail(cause);
+            let _ = sink.close().await;
+        }
+    });
+    SinkStream::new(sender, sent)
+}
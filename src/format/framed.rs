@@ -0,0 +1,240 @@
+//! Framing of connections over byte streams.
+//!
+//! Every `Format` produces a single representation per item and relies on the connection that
+//! carries it to preserve the boundaries between them, as websockets do. Byte streams such as
+//! TCP connections, Unix sockets, pipes and serial links carry no such boundaries, so `Framing`
+//! prefixes each frame with its length in order for the frames to be recovered on the other end.
+//! The result is a connection of frames that can be passed to `decode` or forwarded to and from
+//! the output of `encode`.
+//!
+//! Each frame is written as its length, a big-endian `u32`, followed by the frame itself and,
+//! should a checksum be in use, the big-endian CRC-32 of the frame. Both ends must agree on
+//! whether a checksum is in use.
+
+use crate::kind::SinkStream;
+
+use alloc::sync::Arc;
+use core::{
+    convert::TryInto,
+    mem::size_of,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use crc32fast::hash;
+use futures::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite},
+    ready,
+    stream::unfold,
+    Sink as ISink,
+};
+use std::{
+    io::{self, ErrorKind},
+    sync::Mutex,
+};
+use thiserror::Error;
+
+type Length = u32;
+
+/// The integrity check appended to each frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// Frames are carried as they are, leaving their integrity to the underlying stream.
+    None,
+    /// Frames are followed by their CRC-32, so that those corrupted in transit are detected
+    /// over links that do not detect it themselves, such as serial links.
+    Crc32,
+}
+
+impl Checksum {
+    fn len(self) -> usize {
+        match self {
+            Checksum::None => 0,
+            Checksum::Crc32 => size_of::<u32>(),
+        }
+    }
+}
+
+/// The framing with which frames are written to and read from a byte stream.
+///
+/// Frames larger than `max_frame` bytes are neither sent nor accepted, so that a peer cannot
+/// cause an arbitrarily large frame to be buffered by announcing its length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framing {
+    pub max_frame: usize,
+    pub checksum: Checksum,
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Framing {
+            max_frame: 16 * 1024 * 1024,
+            checksum: Checksum::None,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FramingError {
+    #[error("frame of {size} bytes exceeds the maximum of {max}")]
+    TooLarge { size: usize, max: usize },
+    #[error("frame failed its checksum")]
+    Checksum,
+    #[error("stream ended partway through a frame")]
+    Truncated,
+    #[error("{0}")]
+    Io(#[from] io::Error),
+}
+
+type Failure = Arc<Mutex<Option<FramingError>>>;
+
+impl Framing {
+    /// Frames `io`, which is both read from and written to.
+    pub fn framed<T: AsyncRead + AsyncWrite + Send + 'static>(
+        self,
+        io: T,
+    ) -> SinkStream<Vec<u8>, FramingError, Vec<u8>> {
+        let (reader, writer) = io.split();
+        self.framed_split(reader, writer)
+    }
+
+    /// Frames a byte stream whose halves are read from `reader` and written to `writer`.
+    ///
+    /// Should a frame fail to be read the stream of frames ends, and the error is returned by the
+    /// next attempt to send a frame.
+    pub fn framed_split<
+        R: AsyncRead + Unpin + Sync + Send + 'static,
+        W: AsyncWrite + Unpin + Sync + Send + 'static,
+    >(
+        self,
+        reader: R,
+        writer: W,
+    ) -> SinkStream<Vec<u8>, FramingError, Vec<u8>> {
+        let failure: Failure = Arc::new(Mutex::new(None));
+        SinkStream::new(
+            Writer {
+                writer,
+                framing: self,
+                buffer: vec![],
+                written: 0,
+                failure: failure.clone(),
+            },
+            unfold(Some((reader, failure)), move |state| async move {
+                let (mut reader, failure) = state?;
+                match self.read(&mut reader).await {
+                    Ok(Some(frame)) => Some((frame, Some((reader, failure)))),
+                    Ok(None) => None,
+                    Err(e) => {
+                        failure.lock().unwrap().replace(e);
+                        None
+                    }
+                }
+            }),
+        )
+    }
+
+    /// Reads the next frame, or nothing should the stream end between frames.
+    async fn read<R: AsyncRead + Unpin>(
+        self,
+        reader: &mut R,
+    ) -> Result<Option<Vec<u8>>, FramingError> {
+        let mut length = [0u8; size_of::<Length>()];
+        let mut read = 0;
+        while read < length.len() {
+            match reader.read(&mut length[read..]).await? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(FramingError::Truncated),
+                count => read += count,
+            }
+        }
+        let size = Length::from_be_bytes(length) as usize;
+        if size > self.max_frame {
+            return Err(FramingError::TooLarge {
+                size,
+                max: self.max_frame,
+            });
+        }
+        let mut frame = vec![0u8; size + self.checksum.len()];
+        reader.read_exact(&mut frame).await.map_err(|e| {
+            if e.kind() == ErrorKind::UnexpectedEof {
+                FramingError::Truncated
+            } else {
+                FramingError::Io(e)
+            }
+        })?;
+        if let Checksum::Crc32 = self.checksum {
+            let checksum = u32::from_be_bytes(frame[size..].try_into().unwrap());
+            frame.truncate(size);
+            if hash(&frame) != checksum {
+                return Err(FramingError::Checksum);
+            }
+        }
+        Ok(Some(frame))
+    }
+}
+
+/// Writes frames to a byte stream, one at a time.
+struct Writer<W> {
+    writer: W,
+    framing: Framing,
+    buffer: Vec<u8>,
+    written: usize,
+    failure: Failure,
+}
+
+impl<W: AsyncWrite + Unpin> Writer<W> {
+    /// Writes out the frame currently buffered, if any.
+    fn poll_write_buffer(&mut self, cx: &mut Context) -> Poll<Result<(), FramingError>> {
+        while self.written < self.buffer.len() {
+            match ready!(Pin::new(&mut self.writer).poll_write(cx, &self.buffer[self.written..])) {
+                Ok(0) => return Poll::Ready(Err(io::Error::from(ErrorKind::WriteZero).into())),
+                Ok(count) => self.written += count,
+                Err(e) => return Poll::Ready(Err(e.into())),
+            }
+        }
+        self.buffer.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> ISink<Vec<u8>> for Writer<W> {
+    type Error = FramingError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        if let Some(e) = self.failure.lock().unwrap().take() {
+            return Poll::Ready(Err(e));
+        }
+        self.poll_write_buffer(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Vec<u8>) -> Result<(), Self::Error> {
+        let max = self.framing.max_frame.min(Length::MAX as usize);
+        if item.len() > max {
+            return Err(FramingError::TooLarge {
+                size: item.len(),
+                max,
+            });
+        }
+        self.buffer
+            .extend_from_slice(&(item.len() as Length).to_be_bytes());
+        self.buffer.extend_from_slice(&item);
+        if let Checksum::Crc32 = self.framing.checksum {
+            self.buffer.extend_from_slice(&hash(&item).to_be_bytes());
+        }
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_write_buffer(cx))?;
+        Pin::new(&mut self.writer)
+            .poll_flush(cx)
+            .map_err(FramingError::Io)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_write_buffer(cx))?;
+        Pin::new(&mut self.writer)
+            .poll_close(cx)
+            .map_err(FramingError::Io)
+    }
+}
@@ -17,8 +17,12 @@ impl Format for Json {
     type Representation = String;
     type Error = serde_json::Error;
 
-    fn serialize<T: Serialize>(item: T) -> Self::Representation {
-        serde_json::to_string(&item).unwrap()
+    fn name() -> String {
+        "json".to_owned()
+    }
+
+    fn serialize<T: Serialize>(item: T) -> Result<Self::Representation, Self::Error> {
+        serde_json::to_string(&item)
     }
 
     fn deserialize<'de, T: DeserializeSeed<'de>>(
@@ -0,0 +1,54 @@
+use super::Format;
+
+use serde::{de::DeserializeSeed, Serialize};
+
+use crate::kind::Fallible;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MessagePackError {
+    #[error("{0}")]
+    Encode(#[source] rmp_serde::encode::Error),
+    #[error("{0}")]
+    Decode(#[source] rmp_serde::decode::Error),
+}
+
+/// A format implementing MessagePack.
+///
+/// MessagePack is a compact binary over-the-wire format with a data model close to that of
+/// JSON. It is self describing and implemented in a great many languages, which makes it a
+/// practical choice where peers not written in Rust take part in a connection. Structs are
+/// encoded as maps keyed by field name rather than as arrays so that such peers need not
+/// know the order of fields. This functionality is provided by `rmp-serde`.
+///
+/// For this format to be used the `messagepack` feature must be enabled.
+pub struct MessagePack;
+
+impl Format for MessagePack {
+    type Representation = Vec<u8>;
+    type Error = MessagePackError;
+
+    fn name() -> String {
+        "messagepack".to_owned()
+    }
+
+    fn serialize<T: Serialize>(item: T) -> Result<Self::Representation, Self::Error> {
+        rmp_serde::to_vec_named(&item).map_err(MessagePackError::Encode)
+    }
+
+    fn deserialize<'de, T: DeserializeSeed<'de>>(
+        item: Self::Representation,
+        context: T,
+    ) -> Fallible<T::Value, (Self::Error, Self::Representation)>
+    where
+        T: Sync + Send + 'static,
+    {
+        Box::pin(async move {
+            let mut deserializer = rmp_serde::Deserializer::new(item.as_slice());
+            context
+                .deserialize(&mut deserializer)
+                .map_err(|e| (MessagePackError::Decode(e), item))
+        })
+    }
+}
@@ -11,18 +11,45 @@ pub mod bincode;
 #[cfg(feature = "bincode")]
 #[doc(inline)]
 pub use bincode::Bincode;
+#[cfg(feature = "messagepack")]
+pub mod messagepack;
+#[cfg(feature = "messagepack")]
+#[doc(inline)]
+pub use messagepack::MessagePack;
+#[cfg(feature = "compression")]
+pub mod compressed;
+#[cfg(feature = "compression")]
+#[doc(inline)]
+pub use compressed::Compressed;
+#[cfg(all(feature = "core", not(target_arch = "wasm32")))]
+pub mod encrypted;
+#[cfg(all(feature = "core", not(target_arch = "wasm32")))]
+#[doc(inline)]
+pub use encrypted::Encrypted;
+pub mod capture;
+#[doc(inline)]
+pub use capture::{Capture, Flusher, Replay};
+pub mod erased;
+#[doc(inline)]
+pub use erased::Formats;
+pub mod framed;
+#[doc(inline)]
+pub use framed::Framing;
+mod pending;
+pub use pending::PendingError;
+use pending::{Buffered, Failure};
 
+use anyhow::Error;
 use futures::{
-    channel::mpsc::{unbounded, UnboundedReceiver},
-    future::ok,
-    Future as IFuture, FutureExt, Sink as ISink, SinkExt, Stream as IStream, StreamExt,
-    TryFutureExt,
+    channel::mpsc::{channel, Receiver, SendError},
+    future::ready,
+    Future as IFuture, Sink as ISink, SinkExt, Stream as IStream, StreamExt,
 };
 
 use crate::{
-    channel::{Context, Shim, Target, Waiter},
+    channel::{BufferLimits, Context, Shim, Target, Waiter},
     core::spawn,
-    kind::{Fallible, SinkStream},
+    kind::{Fallible, SinkStream, Stream},
     ErrorBound, Kind,
 };
 
@@ -36,6 +63,32 @@ pub trait UniformStreamSink<T>: ISink<T> + IStream<Item = T> {}
 
 impl<T, U> UniformStreamSink<T> for U where U: ISink<T> + IStream<Item = T> {}
 
+/// A representation of formatted items that can be carried as bytes, as it is over network
+/// connections.
+pub trait Binary: Sized {
+    fn into_bytes(self) -> Vec<u8>;
+    /// Returns the bytes back should they not be a valid representation.
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, Vec<u8>>;
+}
+
+impl Binary for Vec<u8> {
+    fn into_bytes(self) -> Vec<u8> {
+        self
+    }
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, Vec<u8>> {
+        Ok(bytes)
+    }
+}
+
+impl Binary for String {
+    fn into_bytes(self) -> Vec<u8> {
+        String::into_bytes(self)
+    }
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, Vec<u8>> {
+        String::from_utf8(bytes).map_err(|e| e.into_bytes())
+    }
+}
+
 /// A serialization format used in the transport of `Kind`s.
 ///
 /// This is generally a minimal wrapper that encapsulates a `serde` format.
@@ -43,11 +96,18 @@ pub trait Format {
     /// The underlying representation used by this `Format`, i.e. `Vec<u8>` for most
     /// binary formats and `String` for those of a human-readable nature.
     type Representation;
-    /// The failure condition of this format. This may be encountered during deserialization.
+    /// The failure condition of this format. This may be encountered during serialization or
+    /// deserialization.
     type Error: ErrorBound;
 
-    /// Serializes the provided item.
-    fn serialize<T: Serialize>(item: T) -> Self::Representation
+    /// A name identifying this format, exchanged when connections are set up so that both
+    /// ends can agree on which format to use. It must stay the same across builds and
+    /// platforms.
+    fn name() -> String
+    where
+        Self: Sized;
+    /// Serializes the provided item, failing if it cannot be represented in this format.
+    fn serialize<T: Serialize>(item: T) -> Result<Self::Representation, Self::Error>
     where
         Self: Sized;
     /// Deserializes an item from the provided formatted representation.
@@ -60,12 +120,63 @@ pub trait Format {
         Self: Sized;
 }
 
+/// A transformation applied to every representation carried over a single connection in some
+/// `Format`, such as one that authenticates and encrypts the connection.
+pub trait Layer<R>: Sync + Send + 'static {
+    /// Names the format named `format` as carried through this layer, so that both ends of a
+    /// connection only agree on the format where both apply the same layer.
+    fn name(&self, format: String) -> String;
+    /// Wraps a new connection in the format named `format`, taking the representations to be
+    /// sent on it and those received over it. Returns the representations to be sent in their
+    /// stead, and those to be deserialized, where an error ends the connection with it as the
+    /// cause.
+    fn wrap(
+        &self,
+        format: &str,
+        sent: Stream<R>,
+        received: Stream<R>,
+    ) -> (Stream<R>, Stream<Result<R, Error>>);
+}
+
+/// The `Layer` that carries representations as they are.
+pub struct Plain;
+
+impl<R: 'static> Layer<R> for Plain {
+    fn name(&self, format: String) -> String {
+        format
+    }
+    fn wrap(
+        &self,
+        _: &str,
+        sent: Stream<R>,
+        received: Stream<R>,
+    ) -> (Stream<R>, Stream<Result<R, Error>>) {
+        (sent, Box::pin(received.map(Ok)))
+    }
+}
+
+/// Returns the representations of `received` as opened by a layer, ending with the first that
+/// fails to open after recording it as the cause of the connection's failure.
+fn opened<R, W: Waiter>(received: Stream<Result<R, Error>>, failed: W) -> impl IStream<Item = R> {
+    received.scan(failed, |failed, item| {
+        ready(item.map_err(|cause| failed.fail(cause)).ok())
+    })
+}
+
 pub trait ApplyEncode<'de>:
     Sized + UniformStreamSink<<Self as Context<'de>>::Item> + Context<'de>
 where
     <Self as ISink<<Self as Context<'de>>::Item>>::Error: ErrorBound,
 {
-    fn encode<F: Format + Encode<'de, Self>>(self) -> <F as Encode<'de, Self>>::Output;
+    fn encode<F: Format + Encode<'de, Self>>(self) -> <F as Encode<'de, Self>>::Output
+    where
+        F::Representation: 'static;
+
+    /// Encodes as `encode` does, carrying every representation through `layer`.
+    fn encode_through<F: Format + Encode<'de, Self>>(
+        self,
+        layer: Box<dyn Layer<F::Representation>>,
+    ) -> <F as Encode<'de, Self>>::Output;
 }
 
 impl<'de, T> ApplyEncode<'de> for T
@@ -73,8 +184,18 @@ where
     T: UniformStreamSink<<Self as Context<'de>>::Item> + Context<'de>,
     <T as ISink<<Self as Context<'de>>::Item>>::Error: ErrorBound,
 {
-    fn encode<F: Format + Encode<'de, Self>>(self) -> <F as Encode<'de, Self>>::Output {
-        <F as Encode<_>>::encode(self)
+    fn encode<F: Format + Encode<'de, Self>>(self) -> <F as Encode<'de, Self>>::Output
+    where
+        F::Representation: 'static,
+    {
+        <F as Encode<_>>::encode(self, Box::new(Plain))
+    }
+
+    fn encode_through<F: Format + Encode<'de, Self>>(
+        self,
+        layer: Box<dyn Layer<F::Representation>>,
+    ) -> <F as Encode<'de, Self>>::Output {
+        <F as Encode<_>>::encode(self, layer)
     }
 }
 
@@ -87,6 +208,27 @@ pub trait ApplyDecode<'de, K: Kind> {
         F::Representation: Clone + Sync + Send + 'static,
         <Self as ISink<F::Representation>>::Error: ErrorBound,
         T::Item: Sync + Send + 'static;
+
+    fn decode_with_limits<T: Target<'de, K> + Sync + Send + 'static, F: Format + 'static>(
+        self,
+        limits: BufferLimits,
+    ) -> <F as Decode<'de, Self, K>>::Output
+    where
+        Self: UniformStreamSink<F::Representation> + Sync + Send + Sized + 'static,
+        F::Representation: Clone + Sync + Send + 'static,
+        <Self as ISink<F::Representation>>::Error: ErrorBound,
+        T::Item: Sync + Send + 'static;
+
+    /// Decodes as `decode` does, carrying every representation through `layer`.
+    fn decode_through<T: Target<'de, K> + Sync + Send + 'static, F: Format + 'static>(
+        self,
+        layer: Box<dyn Layer<F::Representation>>,
+    ) -> <F as Decode<'de, Self, K>>::Output
+    where
+        Self: UniformStreamSink<F::Representation> + Sync + Send + Sized + 'static,
+        F::Representation: Clone + Sync + Send + 'static,
+        <Self as ISink<F::Representation>>::Error: ErrorBound,
+        T::Item: Sync + Send + 'static;
 }
 
 impl<'de, U, K: Kind> ApplyDecode<'de, K> for U {
@@ -99,7 +241,33 @@ impl<'de, U, K: Kind> ApplyDecode<'de, K> for U {
         <Self as ISink<F::Representation>>::Error: ErrorBound,
         T::Item: Sync + Send,
     {
-        <F as Decode<'de, Self, K>>::decode::<T>(self)
+        <F as Decode<'de, Self, K>>::decode::<T>(self, BufferLimits::default(), Box::new(Plain))
+    }
+
+    fn decode_with_limits<T: Target<'de, K> + Sync + Send + 'static, F: Format + 'static>(
+        self,
+        limits: BufferLimits,
+    ) -> <F as Decode<'de, Self, K>>::Output
+    where
+        Self: UniformStreamSink<F::Representation> + Sync + Send + Sized + 'static,
+        F::Representation: Clone + Sync + Send,
+        <Self as ISink<F::Representation>>::Error: ErrorBound,
+        T::Item: Sync + Send,
+    {
+        <F as Decode<'de, Self, K>>::decode::<T>(self, limits, Box::new(Plain))
+    }
+
+    fn decode_through<T: Target<'de, K> + Sync + Send + 'static, F: Format + 'static>(
+        self,
+        layer: Box<dyn Layer<F::Representation>>,
+    ) -> <F as Decode<'de, Self, K>>::Output
+    where
+        Self: UniformStreamSink<F::Representation> + Sync + Send + Sized + 'static,
+        F::Representation: Clone + Sync + Send,
+        <Self as ISink<F::Representation>>::Error: ErrorBound,
+        T::Item: Sync + Send,
+    {
+        <F as Decode<'de, Self, K>>::decode::<T>(self, BufferLimits::default(), layer)
     }
 }
 
@@ -110,7 +278,11 @@ where
 {
     type Output: IFuture<Output = Result<K, K::ConstructError>>;
 
-    fn decode<T: Target<'de, K> + Sync + Send + 'static>(input: C) -> Self::Output
+    fn decode<T: Target<'de, K> + Sync + Send + 'static>(
+        input: C,
+        limits: BufferLimits,
+        layer: Box<dyn Layer<Self::Representation>>,
+    ) -> Self::Output
     where
         T::Item: Sync + Send;
 }
@@ -123,7 +295,7 @@ where
     type Output: IStream<Item = <Self as Format>::Representation>
         + ISink<Self::Representation, Error = EncodeError<Self, <C as Context<'de>>::Item, C>>;
 
-    fn encode(input: C) -> Self::Output;
+    fn encode(input: C, layer: Box<dyn Layer<Self::Representation>>) -> Self::Output;
 }
 
 impl<
@@ -133,42 +305,60 @@ impl<
         K: Kind,
     > Decode<'de, C, K> for T
 where
-    Self::Representation: Sync + Send + Clone,
+    Self::Representation: Sync + Send + Clone + 'static,
     <C as ISink<<Self as Format>::Representation>>::Error: ErrorBound,
 {
     type Output = Fallible<K, K::ConstructError>;
 
-    fn decode<U: Target<'de, K> + Sync + Send + 'static>(input: C) -> Self::Output
+    fn decode<U: Target<'de, K> + Sync + Send + 'static>(
+        input: C,
+        limits: BufferLimits,
+        layer: Box<dyn Layer<Self::Representation>>,
+    ) -> Self::Output
     where
         U::Item: Sync + Send,
     {
-        let shim = U::new_shim();
+        let shim = U::new_shim_with_limits(limits);
         let context = shim.context();
+        let waiter = context.clone();
+        let failed = context.clone();
         let (sink, stream) = input.split();
+        let (sender, receiver) = channel(limits.connection);
+        let (sent, received) = layer.wrap(&Self::name(), Box::pin(receiver), Box::pin(stream));
+        {
+            let failed = context.clone();
+            spawn(async move {
+                let mut sink =
+                    Box::pin(sink.sink_map_err(EncodeError::<Self, _, C>::from_sink_error));
+                if let Err(e) = sent.map(Ok).forward(&mut sink).await {
+                    failed.fail(e.into_cause());
+                }
+            });
+        }
         Box::pin(
             shim.complete(SinkStream::new(
-                sink.sink_map_err(|_| panic!())
-                    .with::<_, _, _, ()>(|item: U::Item| ok(Self::serialize(item))),
-                stream
-                    .map(move |item| {
-                        let ct = context.clone();
-                        Self::deserialize(item, context.clone())
-                            .or_else(move |(e, item)| {
-                                let context = ct.clone();
-                                let message = format!("{}", e);
-                                let mut data = message.split_whitespace();
-                                if data.next() == Some("ASYNC_WAIT") {
-                                    if let Some(data) = data.next() {
-                                        return context.wait_for(data.to_owned()).then(move |_| {
-                                            Self::deserialize(item, context.clone())
-                                        });
-                                    }
-                                }
-                                panic!(format!("{:?}", e))
-                            })
-                            .unwrap_or_else(|e| panic!(format!("{:?}", e.0)))
-                    })
-                    .buffer_unordered(core::usize::MAX),
+                sender
+                    .sink_map_err(EncodeError::<Self, _, C>::Connection)
+                    .with(move |item: U::Item| {
+                        ready(Self::serialize(item).map_err(EncodeError::from_format_error))
+                    }),
+                Buffered::new(
+                    opened(received, context.clone()),
+                    move |item| Self::deserialize(item, context.clone()),
+                    waiter,
+                    limits,
+                )
+                // A frame that fails to decode ends the connection, closing every fork on it
+                // with the failure as the cause.
+                .scan(failed, |failed, item| {
+                    ready(
+                        item.map_err(|failure| {
+                            failed
+                                .fail(EncodeError::<Self, _, C>::from_failure(failure).into_cause())
+                        })
+                        .ok(),
+                    )
+                }),
             )),
         )
     }
@@ -183,6 +373,10 @@ where
     Format(#[source] T::Error),
     #[error("{0}")]
     Sink(#[source] S::Error),
+    #[error("connection closed: {0}")]
+    Connection(#[source] SendError),
+    #[error("{0}")]
+    Pending(#[source] PendingError),
 }
 
 impl<T: Format, I, S: ISink<I>> Debug for EncodeError<T, I, S>
@@ -196,6 +390,8 @@ where
             match self {
                 EncodeError::Format(e) => format!("Format ({:?})", e),
                 EncodeError::Sink(e) => format!("Sink ({:?})", e),
+                EncodeError::Connection(e) => format!("Connection ({:?})", e),
+                EncodeError::Pending(e) => format!("Pending ({:?})", e),
             }
         )
     }
@@ -211,6 +407,21 @@ where
     fn from_format_error(err: T::Error) -> Self {
         EncodeError::Format(err)
     }
+    fn from_failure(failure: Failure<T::Error>) -> Self {
+        match failure {
+            Failure::Format(e) => EncodeError::from_format_error(e),
+            Failure::Pending(e) => EncodeError::Pending(e),
+        }
+    }
+    /// The underlying error, as recorded as the cause of the connection's failure.
+    fn into_cause(self) -> Error {
+        match self {
+            EncodeError::Format(e) => e.into(),
+            EncodeError::Sink(e) => e.into(),
+            EncodeError::Connection(e) => e.into(),
+            EncodeError::Pending(e) => e.into(),
+        }
+    }
 }
 
 impl<
@@ -219,7 +430,7 @@ impl<
         C: UniformStreamSink<<C as Context<'de>>::Item> + Context<'de> + 'static + Sync + Send + Sized,
     > Encode<'de, C> for T
 where
-    T::Representation: Sync + Send + Clone,
+    T::Representation: Sync + Send + Clone + 'static,
     <C as Context<'de>>::Item: Sync + Send,
     <C as ISink<<C as Context<'de>>::Item>>::Error: ErrorBound,
 {
@@ -229,37 +440,52 @@ where
         Self::Representation,
     >;
 
-    fn encode(input: C) -> Self::Output {
+    fn encode(input: C, layer: Box<dyn Layer<Self::Representation>>) -> Self::Output {
         let ctx = input.context();
+        let limits = input.limits();
         let (sink, stream) = input.split();
-        let (sender, receiver): (_, UnboundedReceiver<<Self as Format>::Representation>) =
-            unbounded();
-        let receiver = receiver
-            .map(move |item: <Self as Format>::Representation| {
-                let ct = ctx.clone();
-                Self::deserialize(item, ctx.clone()).or_else(move |(e, item)| {
-                    let context = ct.clone();
-                    let message = format!("{}", e);
-                    let mut data = message.split_whitespace();
-                    if data.next() == Some("ASYNC_WAIT") {
-                        if let Some(data) = data.next() {
-                            return context
-                                .wait_for(data.to_owned())
-                                .then(move |_| Self::deserialize(item, context.clone()));
-                        }
+        let (sender, receiver): (_, Receiver<<Self as Format>::Representation>) =
+            channel(limits.connection);
+        // An item that fails to serialize ends the connection, failing every fork on it with the
+        // error.
+        let (sent, received) = layer.wrap(
+            &Self::name(),
+            Box::pin(stream.scan(ctx.clone(), move |unserializable, item| {
+                ready(match <Self as Format>::serialize(item) {
+                    Ok(item) => Some(item),
+                    Err(e) => {
+                        unserializable.fail(e.into());
+                        None
                     }
-                    panic!(format!("{:?}", e))
                 })
-            })
-            .buffer_unordered(core::usize::MAX);
-        spawn(
-            receiver
-                .forward(sink.sink_map_err(|e| panic!(format!("{}", e))))
-                .unwrap_or_else(|_| panic!()),
+            })),
+            Box::pin(receiver),
         );
-        SinkStream::new(
-            sender.sink_map_err(|e| panic!(format!("{}", e))),
-            stream.map(<Self as Format>::serialize),
-        )
+        let waiter = ctx.clone();
+        let failed: Box<dyn Fn(Error) + Sync + Send> = {
+            let ctx = ctx.clone();
+            Box::new(move |cause| ctx.fail(cause))
+        };
+        let receiver: Stream<Result<_, EncodeError<Self, _, C>>> = Box::pin(
+            Buffered::new(
+                opened(received, ctx.clone()),
+                move |item| Self::deserialize(item, ctx.clone()),
+                waiter,
+                limits,
+            )
+            .map(|item| item.map_err(EncodeError::from_failure)),
+        );
+        spawn(async move {
+            let mut sink = Box::pin(sink.sink_map_err(EncodeError::from_sink_error));
+            if let Err(cause) = receiver
+                .forward(&mut sink)
+                .await
+                .map_err(EncodeError::into_cause)
+            {
+                (failed)(cause);
+                let _ = sink.close().await;
+            }
+        });
+        SinkStream::new(sender.sink_map_err(EncodeError::Connection), sent)
     }
 }
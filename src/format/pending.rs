@@ -0,0 +1,219 @@
+use crate::{
+    channel::{BufferLimits, ForkHandle, Incoming, Waiter},
+    kind::{Fallible, Stream},
+};
+
+use alloc::sync::Arc;
+use core::{pin::Pin, time::Duration};
+#[cfg(not(all(target_arch = "wasm32", not(feature = "core"))))]
+use futures::future::{select, Either};
+use futures::{
+    stream::{Fuse, FuturesUnordered},
+    task::{Context, Poll},
+    FutureExt, Stream as IStream, StreamExt, TryFutureExt,
+};
+#[cfg(not(all(target_arch = "wasm32", not(feature = "core"))))]
+use futures_timer::Delay;
+use std::collections::{HashMap, VecDeque};
+use thiserror::Error;
+
+/// A failure to deliver frames addressed to a fork that has not been established.
+#[derive(Debug, Error)]
+pub enum PendingError {
+    #[error("underlying channel {0} was not established within {1:?}")]
+    Timeout(ForkHandle, Duration),
+    #[error("more than {1} items buffered awaiting underlying channel {0}")]
+    Overflow(ForkHandle, usize),
+}
+
+pub(crate) enum Failure<E> {
+    Format(E),
+    Pending(PendingError),
+}
+
+type Deserialize<R, E, I> = Arc<dyn Fn(R) -> Fallible<Incoming<I>, (E, R)> + Sync + Send>;
+
+type Decoded<R, I> = (Incoming<I>, R);
+
+type Retry<R, E, I> = (ForkHandle, Fallible<Decoded<R, I>, E>);
+
+/// Deserializes a stream of frames, holding back those addressed to forks that are not yet
+/// established until they are. Frames for any one fork are delivered in the order they arrived.
+pub(crate) struct Buffered<R, E, T, I> {
+    input: Fuse<Stream<Result<Decoded<R, I>, E>>>,
+    deserialize: Deserialize<R, E, I>,
+    waiter: T,
+    limits: BufferLimits,
+    queues: HashMap<ForkHandle, VecDeque<R>>,
+    buffered: usize,
+    waits: FuturesUnordered<Fallible<ForkHandle, PendingError>>,
+    established: VecDeque<ForkHandle>,
+    retry: Option<Retry<R, E, I>>,
+}
+
+impl<R, E, T, I> Unpin for Buffered<R, E, T, I> {}
+
+fn decode<R: Clone + Sync + Send + 'static, E: 'static, I>(
+    deserialize: &Deserialize<R, E, I>,
+    frame: R,
+) -> Fallible<Decoded<R, I>, E>
+where
+    I: Sync + Send + 'static,
+{
+    let copy = frame.clone();
+    Box::pin(
+        deserialize(frame)
+            .map_ok(move |item| (item, copy))
+            .map_err(|(e, _)| e),
+    )
+}
+
+impl<
+        R: Clone + Sync + Send + 'static,
+        E: Sync + Send + 'static,
+        T: Waiter,
+        I: Sync + Send + 'static,
+    > Buffered<R, E, T, I>
+{
+    pub(crate) fn new<
+        S: IStream<Item = R> + Sync + Send + 'static,
+        D: Fn(R) -> Fallible<Incoming<I>, (E, R)> + Sync + Send + 'static,
+    >(
+        input: S,
+        deserialize: D,
+        waiter: T,
+        limits: BufferLimits,
+    ) -> Self {
+        let deserialize: Deserialize<R, E, I> = Arc::new(deserialize);
+        let d = deserialize.clone();
+        let input: Stream<_> = Box::pin(
+            input
+                .map(move |frame| decode(&d, frame))
+                .buffered(limits.connection),
+        );
+        Buffered {
+            input: input.fuse(),
+            deserialize,
+            waiter,
+            limits,
+            queues: HashMap::new(),
+            buffered: 0,
+            waits: FuturesUnordered::new(),
+            established: VecDeque::new(),
+            retry: None,
+        }
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", not(feature = "core"))))]
+    fn wait(&mut self, handle: ForkHandle) {
+        let timeout = self.limits.pending_timeout;
+        self.waits.push(Box::pin(
+            select(self.waiter.wait_for(handle), Delay::new(timeout)).map(
+                move |result| match result {
+                    Either::Left(_) => Ok(handle),
+                    Either::Right(_) => Err(PendingError::Timeout(handle, timeout)),
+                },
+            ),
+        ));
+    }
+
+    /// Vessels have no timer to bound the wait with, so frames are held for as long as the
+    /// fork takes to be established, limited only in number.
+    #[cfg(all(target_arch = "wasm32", not(feature = "core")))]
+    fn wait(&mut self, handle: ForkHandle) {
+        self.waits.push(Box::pin(
+            self.waiter.wait_for(handle).map(move |_| Ok(handle)),
+        ));
+    }
+
+    fn buffer(&mut self, handle: ForkHandle, frame: R) -> Result<(), PendingError> {
+        if !self.queues.contains_key(&handle) {
+            self.wait(handle);
+        }
+        if self.buffered >= self.limits.connection {
+            return Err(PendingError::Overflow(handle, self.limits.connection));
+        }
+        let queue = self.queues.entry(handle).or_default();
+        if queue.len() >= self.limits.pending {
+            return Err(PendingError::Overflow(handle, self.limits.pending));
+        }
+        queue.push_back(frame);
+        self.buffered += 1;
+        Ok(())
+    }
+}
+
+impl<
+        R: Clone + Sync + Send + 'static,
+        E: Sync + Send + 'static,
+        T: Waiter,
+        I: Sync + Send + 'static,
+    > IStream for Buffered<R, E, T, I>
+{
+    type Item = Result<I, Failure<E>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some((handle, retry)) = this.retry.as_mut() {
+                let handle = *handle;
+                let item = match retry.as_mut().poll(cx) {
+                    Poll::Ready(item) => item,
+                    Poll::Pending => return Poll::Pending,
+                };
+                this.retry = None;
+                match item {
+                    Ok((Incoming::Ready(_, item), _)) => return Poll::Ready(Some(Ok(item))),
+                    Ok((Incoming::Pending(_), frame)) => {
+                        this.established
+                            .retain(|established| *established != handle);
+                        this.queues.entry(handle).or_default().push_front(frame);
+                        this.buffered += 1;
+                        this.wait(handle);
+                    }
+                    Err(e) => return Poll::Ready(Some(Err(Failure::Format(e)))),
+                }
+                continue;
+            }
+            if let Some(&handle) = this.established.front() {
+                match this.queues.get_mut(&handle).and_then(VecDeque::pop_front) {
+                    Some(frame) => {
+                        this.buffered -= 1;
+                        this.retry = Some((handle, decode(&this.deserialize, frame)));
+                    }
+                    None => {
+                        this.queues.remove(&handle);
+                        this.established.pop_front();
+                    }
+                }
+                continue;
+            }
+            match this.waits.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(handle))) => {
+                    this.established.push_back(handle);
+                    continue;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(Failure::Pending(e)))),
+                _ => {}
+            }
+            match this.input.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok((Incoming::Ready(handle, item), frame)))) => {
+                    if !this.queues.contains_key(&handle) {
+                        return Poll::Ready(Some(Ok(item)));
+                    }
+                    if let Err(e) = this.buffer(handle, frame) {
+                        return Poll::Ready(Some(Err(Failure::Pending(e))));
+                    }
+                }
+                Poll::Ready(Some(Ok((Incoming::Pending(handle), frame)))) => {
+                    if let Err(e) = this.buffer(handle, frame) {
+                        return Poll::Ready(Some(Err(Failure::Pending(e))));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(Failure::Format(e)))),
+                Poll::Ready(None) if this.queues.is_empty() => return Poll::Ready(None),
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
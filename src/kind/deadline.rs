@@ -0,0 +1,186 @@
+//! Deadlines on remote calls.
+//!
+//! Remote calls made while a `Deadline` is entered carry the time remaining before it to the
+//! callee, and resolve with a `TransportError` should it pass before they return. The callee
+//! enters the deadline in turn while running the call, so that the calls it makes to satisfy
+//! it are bounded by what remains of the caller's budget.
+//!
+//! Vessels have neither a clock nor a timer, so no time passes for deadlines entered in them and
+//! calls they make carry the whole of the budget they were given. Those deadlines are enforced
+//! by the callee, never locally.
+
+use crate::kind::{Fallible, Future};
+
+use anyhow::anyhow;
+use core::{
+    cell::Cell,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+#[cfg(not(all(target_arch = "wasm32", not(feature = "core"))))]
+use futures::future::{select, Either};
+#[cfg(not(all(target_arch = "wasm32", not(feature = "core"))))]
+use futures_timer::Delay;
+
+use super::pipeline::PipelineError;
+
+thread_local! {
+    static CURRENT: Cell<Option<Deadline>> = Cell::new(None);
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "core"))]
+pub(crate) fn now() -> Duration {
+    Duration::from_secs_f64(js_sys::Date::now() / 1000.)
+}
+
+#[cfg(all(target_arch = "wasm32", not(feature = "core")))]
+pub(crate) fn now() -> Duration {
+    Duration::default()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now() -> Duration {
+    use lazy_static::lazy_static;
+    use std::time::Instant;
+
+    lazy_static! {
+        static ref EPOCH: Instant = Instant::now();
+    }
+    EPOCH.elapsed()
+}
+
+/// A point in time by which remote calls must return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Deadline(Duration);
+
+impl Deadline {
+    /// Returns the deadline `timeout` from now.
+    pub fn after(timeout: Duration) -> Self {
+        Deadline(now() + timeout)
+    }
+
+    /// Returns the deadline that remote calls made here would carry, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.with(Cell::get)
+    }
+
+    /// Returns the time remaining before this deadline, which is zero once it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.0.checked_sub(now()).unwrap_or_default()
+    }
+
+    /// Calls `call` with this deadline entered, such that remote calls it makes carry this
+    /// deadline or any earlier one already entered.
+    pub fn enter<T>(self, call: impl FnOnce() -> T) -> T {
+        enter(Some(self), call)
+    }
+
+    /// Wraps `future` such that remote calls made while it is polled carry this deadline or
+    /// any earlier one entered where it is polled.
+    pub fn scope<F: futures::Future>(self, future: F) -> Scoped<F> {
+        Scoped {
+            deadline: Some(self),
+            future: Box::pin(future),
+        }
+    }
+}
+
+fn enter<T>(deadline: Option<Deadline>, call: impl FnOnce() -> T) -> T {
+    let previous = Deadline::current();
+    let deadline = match (previous, deadline) {
+        (Some(previous), Some(deadline)) => Some(previous.min(deadline)),
+        (previous, deadline) => previous.or(deadline),
+    };
+    CURRENT.with(|current| current.set(deadline));
+    let item = call();
+    CURRENT.with(|current| current.set(previous));
+    item
+}
+
+/// A future with a `Deadline` entered while it is polled, returned by `Deadline::scope`.
+pub struct Scoped<F: futures::Future> {
+    deadline: Option<Deadline>,
+    future: Pin<Box<F>>,
+}
+
+impl<F: futures::Future> futures::Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let deadline = self.deadline;
+        enter(deadline, || self.future.as_mut().poll(cx))
+    }
+}
+
+/// An object whose methods are called with a deadline `timeout` from the time of each call.
+///
+/// This does for every call made on a remote `#[object]` trait object, or on any other `Kind`
+/// with remote calls, what `Deadline::enter` does for a single call.
+/// ```ignore
+/// let service = Bounded::new(service, Duration::from_secs(5));
+/// let reply = service.call(|service| service.request(query)).await;
+/// ```
+pub struct Bounded<T> {
+    item: T,
+    timeout: Duration,
+}
+
+impl<T> Bounded<T> {
+    pub fn new(item: T, timeout: Duration) -> Self {
+        Bounded { item, timeout }
+    }
+
+    /// Calls `call` on the wrapped object with a deadline entered.
+    pub fn call<U>(&self, call: impl FnOnce(&T) -> U) -> U {
+        Deadline::after(self.timeout).enter(|| call(&self.item))
+    }
+
+    pub fn into_inner(self) -> T {
+        self.item
+    }
+}
+
+/// Bounds `future` by `deadline`, failing with an error that the deadline was exceeded should
+/// it pass first.
+#[cfg(not(all(target_arch = "wasm32", not(feature = "core"))))]
+pub(crate) fn bound<T: 'static, E: 'static>(
+    deadline: Option<Deadline>,
+    future: impl futures::Future<Output = Result<T, E>> + Sync + Send + 'static,
+    exceeded: impl FnOnce() -> E + Sync + Send + 'static,
+) -> Fallible<T, E> {
+    match deadline {
+        Some(deadline) => Box::pin(async move {
+            let future = Box::pin(deadline.scope(future));
+            match select(future, Delay::new(deadline.remaining())).await {
+                Either::Left((item, _)) => item,
+                Either::Right(_) => Err(exceeded()),
+            }
+        }),
+        None => Box::pin(future),
+    }
+}
+
+/// Enters `deadline` while `future` is polled. Vessels have no timer to enforce it with.
+#[cfg(all(target_arch = "wasm32", not(feature = "core")))]
+pub(crate) fn bound<T: 'static, E: 'static>(
+    deadline: Option<Deadline>,
+    future: impl futures::Future<Output = Result<T, E>> + Sync + Send + 'static,
+    _: impl FnOnce() -> E + Sync + Send + 'static,
+) -> Fallible<T, E> {
+    match deadline {
+        Some(deadline) => Box::pin(deadline.scope(future)),
+        None => Box::pin(future),
+    }
+}
+
+/// Runs a call received with `deadline`, returning its result.
+pub(crate) fn serve<T: Sync + Send + 'static>(
+    deadline: Option<Deadline>,
+    call: impl FnOnce() -> Fallible<T, PipelineError>,
+) -> Future<Result<T, PipelineError>> {
+    let item = enter(deadline, call);
+    bound(deadline, item, || {
+        PipelineError::new(anyhow!("deadline exceeded"))
+    })
+}
@@ -2,7 +2,7 @@ use super::WrappedError;
 use crate::{
     channel::{Channel, ForkHandle},
     kind,
-    kind::{ConstructResult, DeconstructResult, Future},
+    kind::{ConstructResult, DeconstructResult, Future, TransportError},
     Kind,
 };
 
@@ -80,3 +80,40 @@ impl Kind for Error {
         })
     }
 }
+
+#[kind]
+impl Kind for TransportError {
+    type ConstructItem = ForkHandle;
+    type ConstructError = WrappedError<Void>;
+    type ConstructFuture = Future<ConstructResult<Self>>;
+    type DeconstructItem = ();
+    type DeconstructError = WrappedError<Void>;
+    type DeconstructFuture = Future<DeconstructResult<Self>>;
+    fn deconstruct<C: Channel<Self::DeconstructItem, Self::ConstructItem>>(
+        self,
+        mut channel: C,
+    ) -> Self::DeconstructFuture {
+        Box::pin(async move {
+            Ok(channel
+                .send(channel.fork(self.cause).await?)
+                .await
+                .map_err(WrappedError::Send)?)
+        })
+    }
+    fn construct<C: Channel<Self::ConstructItem, Self::DeconstructItem>>(
+        mut channel: C,
+    ) -> Self::ConstructFuture {
+        Box::pin(async move {
+            let handle = channel.next().await.ok_or(WrappedError::Insufficient {
+                got: 0,
+                expected: 1,
+            })?;
+            Ok(TransportError {
+                cause: channel.get_fork::<Error>(handle).await?,
+            })
+        })
+    }
+    fn lost(error: TransportError) -> Option<Self> {
+        Some(error)
+    }
+}
@@ -1,53 +1,575 @@
 use crate::{
-    channel::{Channel, ForkHandle},
+    channel::{Channel, Fork, ForkHandle},
+    core::spawn,
     kind,
-    kind::{ConstructResult, DeconstructResult, Flatten, Future, WrappedError},
+    kind::{
+        deadline::{self, Deadline},
+        pipeline::{Dispatch, Pipe, PipelineError, Retained},
+        ConstructResult, DeconstructResult, Fallible, Flatten, Future, WrappedError,
+    },
+    reflect::MethodIndex,
+    replicate::Track,
     Kind,
 };
 
-use futures::{lock::Mutex, SinkExt, StreamExt, TryFutureExt};
+use anyhow::Error;
+use futures::{
+    channel::{
+        mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    future::{ok, poll_fn, ready, Either},
+    stream::{FuturesOrdered, FuturesUnordered},
+    task::Poll,
+    FutureExt, SinkExt, StreamExt, TryFutureExt,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use alloc::sync::Arc;
+use core::{any::Any, time::Duration};
+use std::{collections::HashMap, sync::Mutex};
+
+type CallId = u32;
+
+/// A request from the construct side of a remote function.
+#[doc(hidden)]
+#[derive(Serialize, Deserialize)]
+pub enum Request<A> {
+    /// Calls the function with the given arguments, within the given time if the caller has a
+    /// deadline.
+    Call(CallId, A, Option<Duration>),
+    /// Calls a method of the eventual result of an earlier call before it has returned.
+    Pipeline {
+        id: CallId,
+        target: CallId,
+        method: MethodIndex,
+        args: ForkHandle,
+        remaining: Option<Duration>,
+    },
+    /// No further calls will be pipelined on the result of the given call.
+    Finish(CallId),
+}
+
+/// A reply from the deconstruct side of a remote function.
+#[doc(hidden)]
+#[derive(Serialize, Deserialize)]
+pub enum Reply {
+    /// The call returned the value on the given fork.
+    Return(ForkHandle),
+    /// As `Return`, the value being retained for pipelined calls until the caller finishes.
+    Retain(ForkHandle),
+    /// The call could not be made.
+    Fail(String),
+}
+
+type ReplyFn<C> = Box<dyn FnOnce(&C, Result<ForkHandle, Error>) + Sync + Send>;
+
+struct Call<C, A> {
+    id: CallId,
+    target: Option<CallId>,
+    request: Box<dyn FnOnce(&C) -> Fallible<Request<A>, Error> + Sync + Send>,
+    reply: ReplyFn<C>,
+}
+
+enum Event<C, A> {
+    Call(Option<Call<C, A>>),
+    Forked(
+        CallId,
+        Option<CallId>,
+        Result<Request<A>, Error>,
+        ReplyFn<C>,
+    ),
+    Reply(Option<(CallId, Reply)>),
+}
+
+#[derive(Default)]
+struct Target {
+    outstanding: usize,
+    returned: bool,
+    retained: bool,
+    failed: bool,
+}
+
+/// The calls of a `Caller` that have not returned or still have pipelined calls
+/// outstanding. A call is finished, releasing its retained result on the remote side,
+/// once it has returned and every call pipelined on it has been sent.
+#[derive(Default)]
+struct Targets {
+    next_id: CallId,
+    calls: HashMap<CallId, Target>,
+}
+
+impl Targets {
+    fn call(&mut self) -> CallId {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.calls.insert(id, Target::default());
+        id
+    }
+
+    fn pipeline(&mut self, target: CallId) -> Option<CallId> {
+        let target = self
+            .calls
+            .get_mut(&target)
+            .filter(|target| !target.returned)?;
+        target.outstanding += 1;
+        Some(self.call())
+    }
+
+    fn failed(&self, id: CallId) -> bool {
+        self.calls.get(&id).map_or(false, |target| target.failed)
+    }
+
+    fn fail(&mut self, id: CallId) -> bool {
+        if let Some(target) = self.calls.get_mut(&id) {
+            target.returned = true;
+            target.failed = true;
+        }
+        self.settle(id)
+    }
+
+    fn returned(&mut self, id: CallId, retained: bool) -> bool {
+        if let Some(target) = self.calls.get_mut(&id) {
+            target.returned = true;
+            target.retained = retained;
+        }
+        self.settle(id)
+    }
+
+    fn sent(&mut self, target: CallId) -> bool {
+        if let Some(target) = self.calls.get_mut(&target) {
+            target.outstanding -= 1;
+        }
+        self.settle(target)
+    }
+
+    /// Forgets a call that has returned with nothing pipelined on it outstanding, returning
+    /// whether the remote side must be told that it is finished.
+    fn settle(&mut self, id: CallId) -> bool {
+        match self.calls.get(&id) {
+            Some(target) if target.returned && target.outstanding == 0 => self
+                .calls
+                .remove(&id)
+                .map_or(false, |target| target.retained),
+            _ => false,
+        }
+    }
+}
+
+/// The construct side of a remote function. Each call is tagged with an
+/// identifier so that any number of calls may be in flight at once on the
+/// same channel, their replies being matched up by that identifier.
+struct Caller<C, A> {
+    calls: UnboundedSender<Call<C, A>>,
+    targets: Arc<Mutex<Targets>>,
+}
+
+impl<C, A> Clone for Caller<C, A> {
+    fn clone(&self) -> Self {
+        Caller {
+            calls: self.calls.clone(),
+            targets: self.targets.clone(),
+        }
+    }
+}
+
+/// Pipelines calls on the eventual result of a call made through a `Caller`.
+struct Pipeline<C, A> {
+    caller: Caller<C, A>,
+    target: CallId,
+}
+
+impl<C, A> Clone for Pipeline<C, A> {
+    fn clone(&self) -> Self {
+        Pipeline {
+            caller: self.caller.clone(),
+            target: self.target,
+        }
+    }
+}
+
+impl<
+        A: Serialize + DeserializeOwned + Sync + Send + Unpin + 'static,
+        C: Channel<(CallId, Reply), Request<A>>,
+    > Pipe for Pipeline<C, A>
+{
+    fn call<K: Kind, R: Kind + Flatten>(&self, method: MethodIndex, args: K) -> Result<R, K> {
+        self.caller.pipeline(self.target, method, args)
+    }
+}
+
+impl<
+        A: Serialize + DeserializeOwned + Sync + Send + Unpin + 'static,
+        C: Channel<(CallId, Reply), Request<A>>,
+    > Caller<C, A>
+{
+    fn new(channel: C) -> Self {
+        let (sender, receiver) = unbounded();
+        let targets = Arc::new(Mutex::new(Targets::default()));
+        spawn(Self::drive(channel, receiver, targets.clone()));
+        Caller {
+            calls: sender,
+            targets,
+        }
+    }
+
+    fn call<U: Kind + Flatten>(
+        &self,
+        args: impl FnOnce(&C) -> Fallible<A, Error> + Sync + Send + 'static,
+    ) -> U {
+        let id = self.targets.lock().unwrap().call();
+        let deadline = Deadline::current();
+        let item = U::flatten_pipelined(
+            self.send(id, None, deadline, move |channel| {
+                let args = args(channel);
+                Box::pin(async move {
+                    Ok(Request::Call(
+                        id,
+                        args.await?,
+                        deadline.map(|deadline| deadline.remaining()),
+                    ))
+                })
+            }),
+            Pipeline {
+                caller: self.clone(),
+                target: id,
+            },
+        );
+        match deadline {
+            Some(deadline) => item.bound(deadline),
+            None => item,
+        }
+    }
+
+    fn pipeline<K: Kind, R: Kind + Flatten>(
+        &self,
+        target: CallId,
+        method: MethodIndex,
+        args: K,
+    ) -> Result<R, K> {
+        let id = match self.targets.lock().unwrap().pipeline(target) {
+            Some(id) => id,
+            None => return Err(args),
+        };
+        let deadline = Deadline::current();
+        let item = R::flatten_pipelined(
+            self.send(id, Some(target), deadline, move |channel| {
+                let args = channel.fork(args);
+                Box::pin(async move {
+                    Ok(Request::Pipeline {
+                        id,
+                        target,
+                        method,
+                        args: args.await?,
+                        remaining: deadline.map(|deadline| deadline.remaining()),
+                    })
+                })
+            }),
+            Pipeline {
+                caller: self.clone(),
+                target: id,
+            },
+        );
+        Ok(match deadline {
+            Some(deadline) => item.bound(deadline),
+            None => item,
+        })
+    }
+
+    fn send<U: Kind>(
+        &self,
+        id: CallId,
+        target: Option<CallId>,
+        deadline: Option<Deadline>,
+        request: impl FnOnce(&C) -> Fallible<Request<A>, Error> + Sync + Send + 'static,
+    ) -> Fallible<U, Error> {
+        let (sender, receiver) = oneshot::channel::<Fallible<U, Error>>();
+        let _ = self.calls.unbounded_send(Call {
+            id,
+            target,
+            request: Box::new(request),
+            reply: Box::new(move |channel, handle| {
+                let _ = sender.send(match handle {
+                    Ok(handle) => Box::pin(channel.get_fork::<U>(handle).map_err(Error::from)),
+                    Err(e) => Box::pin(ready(Err(e))),
+                });
+            }),
+        });
+        deadline::bound(deadline, async move { receiver.await?.await }, || {
+            anyhow::anyhow!("deadline exceeded")
+        })
+    }
+
+    async fn drive(
+        mut channel: C,
+        mut calls: UnboundedReceiver<Call<C, A>>,
+        targets: Arc<Mutex<Targets>>,
+    ) {
+        let mut pending = HashMap::new();
+        // Requests are sent in the order they were made, so that a pipelined call is never
+        // sent ahead of the call it was made on.
+        let mut forking = FuturesOrdered::new();
+        let mut open = true;
+        while open || !pending.is_empty() || !forking.is_empty() {
+            let event = poll_fn(|cx| {
+                if let Poll::Ready(reply) = channel.poll_next_unpin(cx) {
+                    return Poll::Ready(Event::Reply(reply));
+                }
+                if let Poll::Ready(Some((id, target, request, reply))) = forking.poll_next_unpin(cx)
+                {
+                    return Poll::Ready(Event::Forked(id, target, request, reply));
+                }
+                if open {
+                    if let Poll::Ready(call) = calls.poll_next_unpin(cx) {
+                        return Poll::Ready(Event::Call(call));
+                    }
+                }
+                Poll::Pending
+            })
+            .await;
+            match event {
+                Event::Call(Some(Call {
+                    id,
+                    target,
+                    request,
+                    reply,
+                })) => {
+                    forking
+                        .push((request)(&channel).map(move |request| (id, target, request, reply)));
+                }
+                Event::Call(None) => open = false,
+                Event::Forked(id, target, request, reply) => {
+                    let request = match target {
+                        Some(target) if targets.lock().unwrap().failed(target) => Err(
+                            PipelineError::new(anyhow::anyhow!("pipelined on a failed call"))
+                                .into(),
+                        ),
+                        _ => request,
+                    };
+                    match request {
+                        Ok(request) => {
+                            if channel.send(request).await.is_ok() {
+                                pending.insert(id, reply);
+                            }
+                        }
+                        Err(e) => {
+                            targets.lock().unwrap().fail(id);
+                            (reply)(&channel, Err(e));
+                        }
+                    }
+                    if let Some(target) = target {
+                        if targets.lock().unwrap().sent(target) {
+                            let _ = channel.send(Request::Finish(target)).await;
+                        }
+                    }
+                }
+                Event::Reply(Some((id, reply))) => {
+                    let (handle, retained) = match reply {
+                        Reply::Return(handle) => (Ok(handle), false),
+                        Reply::Retain(handle) => (Ok(handle), true),
+                        Reply::Fail(e) => {
+                            (Err(PipelineError::new(anyhow::anyhow!(e)).into()), false)
+                        }
+                    };
+                    let finish = match handle {
+                        Ok(_) => targets.lock().unwrap().returned(id, retained),
+                        Err(_) => targets.lock().unwrap().fail(id),
+                    };
+                    if finish {
+                        let _ = channel.send(Request::Finish(id)).await;
+                    }
+                    if let Some(reply) = pending.remove(&id) {
+                        (reply)(&channel, handle);
+                    }
+                }
+                Event::Reply(None) => {
+                    // The channel closing with calls outstanding means the connection was lost.
+                    for (_, reply) in pending.drain() {
+                        (reply)(&channel, Err(anyhow::anyhow!("connection lost")));
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// The number of results a caller may have retained at once, beyond which results are returned
+/// without being retained so that a caller that never finishes with them cannot exhaust memory.
+const RETAINED: usize = 1024;
+
+/// A call pipelined on the result of a call that has not yet returned, with its deadline.
+type Queued = (CallId, MethodIndex, ForkHandle, Option<Deadline>);
+
+/// The deconstruct side of a remote function. Calls are started as they arrive
+/// and their results are forked back to the caller, tagged with the identifier
+/// of the originating call, in whatever order they complete.
+///
+/// Results with methods are retained until the caller finishes with them, calls
+/// pipelined on a result being queued until it is available. Once `RETAINED` results are
+/// retained, further results are returned without being retained, calls the caller pipelines
+/// on them after they return failing.
+async fn serve<
+    A: Serialize + DeserializeOwned + Sync + Send + Unpin + 'static,
+    U: Kind + Flatten,
+    C: Channel<Request<A>, (CallId, Reply)>,
+    F: FnMut(&C, A) -> Fallible<U, PipelineError>,
+>(
+    mut channel: C,
+    mut call: F,
+) -> Result<(), WrappedError<U::DeconstructError>> {
+    let mut calls = FuturesUnordered::new();
+    let mut retained: HashMap<CallId, Box<dyn Dispatch<C>>> = HashMap::new();
+    let mut waiting: HashMap<CallId, Vec<Queued>> = HashMap::new();
+    let mut open = true;
+    while open || !calls.is_empty() {
+        let event = poll_fn(|cx| {
+            if open {
+                if let Poll::Ready(item) = channel.poll_next_unpin(cx) {
+                    return Poll::Ready(Either::Left(item));
+                }
+            }
+            if let Poll::Ready(Some(reply)) = calls.poll_next_unpin(cx) {
+                return Poll::Ready(Either::Right(reply));
+            }
+            Poll::Pending
+        })
+        .await;
+        match event {
+            Either::Left(Some(Request::Call(id, args, remaining))) => {
+                waiting.insert(id, vec![]);
+                // The result is wrapped within the deadline so that it is bounded by it.
+                calls.push(Box::pin(
+                    deadline::serve(remaining.map(Deadline::after), || {
+                        Box::pin(call(&channel, args).map_ok(Retained::new))
+                    })
+                    .map(move |item| (id, item)),
+                ) as Future<_>);
+            }
+            Either::Left(Some(Request::Pipeline {
+                id,
+                target,
+                method,
+                args,
+                remaining,
+            })) => {
+                let deadline = remaining.map(Deadline::after);
+                if let Some(object) = retained.get(&target) {
+                    waiting.insert(id, vec![]);
+                    calls.push(Box::pin(
+                        deadline::serve(deadline, || object.dispatch(method, args, &channel))
+                            .map(move |item| (id, item)),
+                    ));
+                } else if let Some(queue) = waiting.get_mut(&target) {
+                    queue.push((id, method, args, deadline));
+                    waiting.insert(id, vec![]);
+                } else {
+                    channel
+                        .send((id, Reply::Fail("no such call to pipeline on".to_owned())))
+                        .await
+                        .map_err(WrappedError::Send)?;
+                }
+            }
+            Either::Left(Some(Request::Finish(id))) => {
+                retained.remove(&id);
+            }
+            Either::Left(None) => open = false,
+            Either::Right((id, item)) => {
+                let queued = waiting.remove(&id).unwrap_or_default();
+                let (reply, mut failed) = match item {
+                    Ok(item) => {
+                        let (fork, object) = item.split();
+                        let handle = fork(&channel).await;
+                        match (handle, object) {
+                            (Ok(handle), Some(object)) => {
+                                for (id, method, args, deadline) in queued {
+                                    calls.push(Box::pin(
+                                        deadline::serve(deadline, || {
+                                            object.dispatch(method, args, &channel)
+                                        })
+                                        .map(move |item| (id, item)),
+                                    ));
+                                }
+                                if retained.len() < RETAINED {
+                                    retained.insert(id, object);
+                                    (Reply::Retain(handle), vec![])
+                                } else {
+                                    (Reply::Return(handle), vec![])
+                                }
+                            }
+                            (Ok(handle), None) => (Reply::Return(handle), queued),
+                            (Err(e), _) => (Reply::Fail(e.to_string()), queued),
+                        }
+                    }
+                    Err(e) => (Reply::Fail(e.to_string()), queued),
+                };
+                channel
+                    .send((id, reply))
+                    .await
+                    .map_err(WrappedError::Send)?;
+                // Calls queued on a failed call fail in turn, as do those queued on them.
+                while let Some((id, _, _, _)) = failed.pop() {
+                    failed.extend(waiting.remove(&id).unwrap_or_default());
+                    channel
+                        .send((
+                            id,
+                            Reply::Fail(
+                                "pipelined on a call that failed or has no methods".to_owned(),
+                            ),
+                        ))
+                        .await
+                        .map_err(WrappedError::Send)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
 
 use void::Void;
 
+fn called_twice() -> PipelineError {
+    PipelineError::new(anyhow::anyhow!("FnOnce called more than once"))
+}
+
+/// Constructs the argument at `index` of a call from the handles sent with it, failing the call
+/// if the peer sent too few.
+fn argument<K: Kind, C: Fork>(
+    channel: &C,
+    handles: &[ForkHandle],
+    index: usize,
+) -> Fallible<K, PipelineError> {
+    match handles.get(index) {
+        Some(handle) => Box::pin(channel.get_fork::<K>(*handle).map_err(PipelineError::new)),
+        None => Box::pin(ready(Err(PipelineError::new(anyhow::anyhow!(
+            "call is missing argument {}",
+            index
+        ))))),
+    }
+}
+
 #[kind]
 impl<U: Kind + Flatten> Kind for Box<dyn Fn() -> U + Send + Sync> {
-    type ConstructItem = ForkHandle;
+    type ConstructItem = (CallId, Reply);
     type ConstructError = Void;
     type ConstructFuture = Future<ConstructResult<Self>>;
-    type DeconstructItem = ();
+    type DeconstructItem = Request<()>;
     type DeconstructError = WrappedError<<U as Kind>::DeconstructError>;
     type DeconstructFuture = Future<DeconstructResult<Self>>;
 
     fn deconstruct<C: Channel<Self::DeconstructItem, Self::ConstructItem>>(
         self,
-        mut channel: C,
+        channel: C,
     ) -> Self::DeconstructFuture {
-        Box::pin(async move {
-            while let Some(()) = channel.next().await {
-                channel
-                    .send(channel.fork((self)()).await?)
-                    .await
-                    .map_err(WrappedError::Send)?;
-            }
-            Ok(())
-        })
+        Box::pin(serve(channel, move |_, ()| Box::pin(ok((self)()))))
     }
     fn construct<C: Channel<Self::ConstructItem, Self::DeconstructItem>>(
         channel: C,
     ) -> Self::ConstructFuture {
         Box::pin(async move {
-            let channel = Arc::new(Mutex::new(channel));
-            let closure: Box<dyn Fn() -> U + Send + Sync> = Box::new(move || {
-                let channel = channel.clone();
-                U::flatten(async move {
-                    let mut channel = channel.lock().await;
-                    channel.send(()).unwrap_or_else(|_| panic!()).await;
-                    let handle = channel.next().await.expect("test2");
-                    channel.get_fork(handle).await
-                })
-            });
+            let caller = Caller::new(channel);
+            let closure: Box<dyn Fn() -> U + Send + Sync> =
+                Box::new(move || caller.call(|_| Box::pin(ok(()))));
             Ok(closure)
         })
     }
@@ -55,41 +577,26 @@ impl<U: Kind + Flatten> Kind for Box<dyn Fn() -> U + Send + Sync> {
 
 #[kind]
 impl<U: Kind + Flatten> Kind for Box<dyn FnMut() -> U + Send + Sync> {
-    type ConstructItem = ForkHandle;
+    type ConstructItem = (CallId, Reply);
     type ConstructError = Void;
     type ConstructFuture = Future<ConstructResult<Self>>;
-    type DeconstructItem = ();
+    type DeconstructItem = Request<()>;
     type DeconstructError = WrappedError<<U as Kind>::DeconstructError>;
     type DeconstructFuture = Future<DeconstructResult<Self>>;
 
     fn deconstruct<C: Channel<Self::DeconstructItem, Self::ConstructItem>>(
         mut self,
-        mut channel: C,
+        channel: C,
     ) -> Self::DeconstructFuture {
-        Box::pin(async move {
-            while let Some(()) = channel.next().await {
-                channel
-                    .send(channel.fork((self)()).await?)
-                    .await
-                    .map_err(WrappedError::Send)?;
-            }
-            Ok(())
-        })
+        Box::pin(serve(channel, move |_, ()| Box::pin(ok((self)()))))
     }
     fn construct<C: Channel<Self::ConstructItem, Self::DeconstructItem>>(
         channel: C,
     ) -> Self::ConstructFuture {
         Box::pin(async move {
-            let channel = Arc::new(Mutex::new(channel));
-            let closure: Box<dyn FnMut() -> U + Send + Sync> = Box::new(move || {
-                let channel = channel.clone();
-                U::flatten(async move {
-                    let mut channel = channel.lock().await;
-                    channel.send(()).unwrap_or_else(|_| panic!()).await;
-                    let handle = channel.next().await.expect("test2");
-                    channel.get_fork(handle).await
-                })
-            });
+            let caller = Caller::new(channel);
+            let closure: Box<dyn FnMut() -> U + Send + Sync> =
+                Box::new(move || caller.call(|_| Box::pin(ok(()))));
             Ok(closure)
         })
     }
@@ -97,38 +604,30 @@ impl<U: Kind + Flatten> Kind for Box<dyn FnMut() -> U + Send + Sync> {
 
 #[kind]
 impl<U: Kind + Flatten> Kind for Box<dyn FnOnce() -> U + Send + Sync> {
-    type ConstructItem = ForkHandle;
+    type ConstructItem = (CallId, Reply);
     type ConstructError = Void;
     type ConstructFuture = Future<ConstructResult<Self>>;
-    type DeconstructItem = ();
+    type DeconstructItem = Request<()>;
     type DeconstructError = WrappedError<<U as Kind>::DeconstructError>;
     type DeconstructFuture = Future<DeconstructResult<Self>>;
 
     fn deconstruct<C: Channel<Self::DeconstructItem, Self::ConstructItem>>(
         self,
-        mut channel: C,
+        channel: C,
     ) -> Self::DeconstructFuture {
-        Box::pin(async move {
-            if let Some(()) = channel.next().await {
-                channel
-                    .send(channel.fork((self)()).await?)
-                    .await
-                    .map_err(WrappedError::Send)?;
-            }
-            Ok(())
-        })
+        let mut this = Some(self);
+        Box::pin(serve(channel, move |_, ()| match this.take() {
+            Some(this) => Box::pin(ok((this)())),
+            None => Box::pin(ready(Err(called_twice()))),
+        }))
     }
     fn construct<C: Channel<Self::ConstructItem, Self::DeconstructItem>>(
-        mut channel: C,
+        channel: C,
     ) -> Self::ConstructFuture {
         Box::pin(async move {
-            let closure: Box<dyn FnOnce() -> U + Send + Sync> = Box::new(move || {
-                U::flatten(async move {
-                    channel.send(()).unwrap_or_else(|_| panic!()).await;
-                    let handle = channel.next().await.expect("test2");
-                    channel.get_fork(handle).await
-                })
-            });
+            let caller = Caller::new(channel);
+            let closure: Box<dyn FnOnce() -> U + Send + Sync> =
+                Box::new(move || caller.call(|_| Box::pin(ok(()))));
             Ok(closure)
         })
     }
@@ -136,46 +635,55 @@ impl<U: Kind + Flatten> Kind for Box<dyn FnOnce() -> U + Send + Sync> {
 
 #[kind]
 impl<U: Kind + Flatten> Kind for Arc<Box<dyn Fn() -> U + Send + Sync>> {
-    type ConstructItem = ForkHandle;
+    type ConstructItem = (CallId, Reply);
     type ConstructError = Void;
     type ConstructFuture = Future<ConstructResult<Self>>;
-    type DeconstructItem = ();
+    type DeconstructItem = Request<()>;
     type DeconstructError = WrappedError<<U as Kind>::DeconstructError>;
     type DeconstructFuture = Future<DeconstructResult<Self>>;
 
     fn deconstruct<C: Channel<Self::DeconstructItem, Self::ConstructItem>>(
         self,
-        mut channel: C,
+        channel: C,
     ) -> Self::DeconstructFuture {
-        Box::pin(async move {
-            while let Some(()) = channel.next().await {
-                channel
-                    .send(channel.fork((self)()).await?)
-                    .await
-                    .map_err(WrappedError::Send)?;
-            }
-            Ok(())
-        })
+        Box::pin(serve(channel, move |_, ()| Box::pin(ok((self)()))))
     }
     fn construct<C: Channel<Self::ConstructItem, Self::DeconstructItem>>(
         channel: C,
     ) -> Self::ConstructFuture {
         Box::pin(async move {
-            let channel = Arc::new(Mutex::new(channel));
-            let closure: Arc<Box<dyn Fn() -> U + Send + Sync>> = Arc::new(Box::new(move || {
-                let channel = channel.clone();
-                U::flatten(async move {
-                    let mut channel = channel.lock().await;
-                    channel.send(()).unwrap_or_else(|_| panic!()).await;
-                    let handle = channel.next().await.expect("test2");
-                    channel.get_fork(handle).await
-                })
-            }));
+            let caller = Caller::new(channel);
+            let closure: Arc<Box<dyn Fn() -> U + Send + Sync>> =
+                Arc::new(Box::new(move || caller.call(|_| Box::pin(ok(())))));
             Ok(closure)
         })
     }
 }
 
+// A closure's remote proxy is served for as long as the peer holds it, so a guard held by the
+// closure is dropped along with it.
+macro_rules! track_impl {
+    ($($name:ident)*) => {
+        track_impl!(@ Box<dyn Fn($($name),*) -> U + Send + Sync>, [], ($($name)*),);
+        track_impl!(@ Box<dyn FnMut($($name),*) -> U + Send + Sync>, [mut], ($($name)*),);
+        track_impl!(@ Box<dyn FnOnce($($name),*) -> U + Send + Sync>, [], ($($name)*),);
+        track_impl!(@ Arc<Box<dyn Fn($($name),*) -> U + Send + Sync>>, [], ($($name)*), Arc::new);
+    };
+    (@ $ty:ty, [$($mut:tt)?], ($($name:ident)*), $($wrap:tt)*) => {
+        #[allow(non_snake_case)]
+        impl<U: Kind + Flatten, $($name: Kind),*> Track for $ty {
+            fn track($($mut)? self, guard: Arc<dyn Any + Sync + Send>) -> Self {
+                $($wrap)*(Box::new(move |$($name),*| {
+                    let _ = &guard;
+                    (self)($($name),*)
+                }))
+            }
+        }
+    };
+}
+
+track_impl!();
+
 macro_rules! functions_impl {
     ($($len:expr => ($($n:tt $name:ident $nn:ident)+))+) => {$(
         #[allow(non_snake_case)]
@@ -183,48 +691,34 @@ macro_rules! functions_impl {
         impl<U: Kind + Flatten, $($name),+> Kind for Box<dyn Fn($($name),+) -> U + Send + Sync>
             where $($name: Kind),+
         {
-            type ConstructItem = ForkHandle;
+            type ConstructItem = (CallId, Reply);
             type ConstructError = Void;
             type ConstructFuture = Future<ConstructResult<Self>>;
-            type DeconstructItem = Vec<ForkHandle>;
-            type DeconstructError = Void;
+            type DeconstructItem = Request<Vec<ForkHandle>>;
+            type DeconstructError = WrappedError<<U as Kind>::DeconstructError>;
             type DeconstructFuture = Future<DeconstructResult<Self>>;
 
             fn deconstruct<C: Channel<Self::DeconstructItem, Self::ConstructItem>>(
                 self,
-                mut channel: C,
+                channel: C,
             ) -> Self::DeconstructFuture {
-                Box::pin(async move {
-                    while let Some(handles) = channel.next().await {
-                        channel
-                            .send(
-                                channel
-                                    .fork((self)($(channel.get_fork::<$name>(handles[$n as usize]).await.unwrap()),+))
-                                    .await
-                                    .unwrap(),
-                            )
-                            .unwrap_or_else(|_| panic!())
-                            .await;
-                    }
-                    Ok(())
-                })
+                let this = Arc::new(self);
+                Box::pin(serve(channel, move |channel, handles: Vec<ForkHandle>| {
+                    let this = this.clone();
+                    $(let $nn = argument::<$name, _>(channel, &handles, $n);)+
+                    Box::pin(async move { Ok((this)($($nn.await?),+)) })
+                }))
             }
             fn construct<C: Channel<Self::ConstructItem, Self::DeconstructItem>>(
                 channel: C,
             ) -> Self::ConstructFuture {
                 Box::pin(async move {
-                    let channel = Arc::new(Mutex::new(channel));
+                    let caller = Caller::new(channel);
                     let closure: Box<dyn Fn($($name),+) -> U + Send + Sync> =
                         Box::new(move |$($name),+| {
-                            let channel = channel.clone();
-                            U::flatten(async move {
-                                let mut channel = channel.lock().await;
-                                let handles = vec![
-                                    $(channel.fork::<$name>($name).await.unwrap()),+
-                                ];
-                                channel.send(handles).unwrap_or_else(|_| panic!()).await;
-                                let handle = channel.next().await.expect("test2");
-                                channel.get_fork(handle).await
+                            caller.call(move |channel: &C| {
+                                $(let $nn = channel.fork::<$name>($name);)+
+                                Box::pin(async move { Ok(vec![$($nn.await?),+]) })
                             })
                         });
                     Ok(closure)
@@ -236,48 +730,37 @@ macro_rules! functions_impl {
         impl<U: Kind + Flatten, $($name),+> Kind for Box<dyn FnMut($($name),+) -> U + Send + Sync>
             where $($name: Kind),+
         {
-            type ConstructItem = ForkHandle;
+            type ConstructItem = (CallId, Reply);
             type ConstructError = Void;
             type ConstructFuture = Future<ConstructResult<Self>>;
-            type DeconstructItem = Vec<ForkHandle>;
-            type DeconstructError = Void;
+            type DeconstructItem = Request<Vec<ForkHandle>>;
+            type DeconstructError = WrappedError<<U as Kind>::DeconstructError>;
             type DeconstructFuture = Future<DeconstructResult<Self>>;
 
             fn deconstruct<C: Channel<Self::DeconstructItem, Self::ConstructItem>>(
-                mut self,
-                mut channel: C,
+                self,
+                channel: C,
             ) -> Self::DeconstructFuture {
-                Box::pin(async move {
-                    while let Some(handles) = channel.next().await {
-                        channel
-                            .send(
-                                channel
-                                    .fork((self)($(channel.get_fork::<$name>(handles[$n as usize]).await.unwrap()),+))
-                                    .await
-                                    .unwrap(),
-                            )
-                            .unwrap_or_else(|_| panic!())
-                            .await;
-                    }
-                    Ok(())
-                })
+                let this = Arc::new(Mutex::new(self));
+                Box::pin(serve(channel, move |channel, handles: Vec<ForkHandle>| {
+                    let this = this.clone();
+                    $(let $nn = argument::<$name, _>(channel, &handles, $n);)+
+                    Box::pin(async move {
+                        $(let $nn = $nn.await?;)+
+                        Ok((this.lock().unwrap())($($nn),+))
+                    })
+                }))
             }
             fn construct<C: Channel<Self::ConstructItem, Self::DeconstructItem>>(
                 channel: C,
             ) -> Self::ConstructFuture {
                 Box::pin(async move {
-                    let channel = Arc::new(Mutex::new(channel));
+                    let caller = Caller::new(channel);
                     let closure: Box<dyn FnMut($($name),+) -> U + Send + Sync> =
                         Box::new(move |$($name),+| {
-                            let channel = channel.clone();
-                            U::flatten(async move {
-                                let mut channel = channel.lock().await;
-                                let handles = vec![
-                                    $(channel.fork::<$name>($name).await.unwrap()),+
-                                ];
-                                channel.send(handles).unwrap_or_else(|_| panic!()).await;
-                                let handle = channel.next().await.expect("test2");
-                                channel.get_fork(handle).await
+                            caller.call(move |channel: &C| {
+                                $(let $nn = channel.fork::<$name>($name);)+
+                                Box::pin(async move { Ok(vec![$($nn.await?),+]) })
                             })
                         });
                     Ok(closure)
@@ -289,44 +772,37 @@ macro_rules! functions_impl {
         impl<U: Kind + Flatten, $($name),+> Kind for Box<dyn FnOnce($($name),+) -> U + Send + Sync>
             where $($name: Kind),+
         {
-            type ConstructItem = ForkHandle;
+            type ConstructItem = (CallId, Reply);
             type ConstructError = Void;
             type ConstructFuture = Future<ConstructResult<Self>>;
-            type DeconstructItem = Vec<ForkHandle>;
-            type DeconstructError = Void;
+            type DeconstructItem = Request<Vec<ForkHandle>>;
+            type DeconstructError = WrappedError<<U as Kind>::DeconstructError>;
             type DeconstructFuture = Future<DeconstructResult<Self>>;
 
             fn deconstruct<C: Channel<Self::DeconstructItem, Self::ConstructItem>>(
                 self,
-                mut channel: C,
+                channel: C,
             ) -> Self::DeconstructFuture {
-                Box::pin(async move {
-                    let handles = channel.next().await.unwrap();
-                    channel
-                        .send(
-                            channel
-                                .fork((self)($(channel.get_fork::<$name>(handles[$n as usize]).await.unwrap()),+))
-                                .await
-                                .unwrap(),
-                        )
-                        .unwrap_or_else(|_| panic!())
-                        .await;
-                    Ok(())
-                })
+                let mut this = Some(self);
+                Box::pin(serve(channel, move |channel, handles: Vec<ForkHandle>| {
+                    let this = this.take();
+                    $(let $nn = argument::<$name, _>(channel, &handles, $n);)+
+                    Box::pin(async move {
+                        let this = this.ok_or_else(called_twice)?;
+                        Ok((this)($($nn.await?),+))
+                    })
+                }))
             }
             fn construct<C: Channel<Self::ConstructItem, Self::DeconstructItem>>(
-                mut channel: C,
+                channel: C,
             ) -> Self::ConstructFuture {
                 Box::pin(async move {
+                    let caller = Caller::new(channel);
                     let closure: Box<dyn FnOnce($($name),+) -> U + Send + Sync> =
                         Box::new(move |$($name),+| {
-                            U::flatten(async move {
-                                let handles = vec![
-                                    $(channel.fork::<$name>($name).await.unwrap()),+
-                                ];
-                                channel.send(handles).unwrap_or_else(|_| panic!()).await;
-                                let handle = channel.next().await.expect("test2");
-                                channel.get_fork(handle).await
+                            caller.call(move |channel: &C| {
+                                $(let $nn = channel.fork::<$name>($name);)+
+                                Box::pin(async move { Ok(vec![$($nn.await?),+]) })
                             })
                         });
                     Ok(closure)
@@ -338,53 +814,41 @@ macro_rules! functions_impl {
         impl<U: Kind + Flatten, $($name),+> Kind for Arc<Box<dyn Fn($($name),+) -> U + Send + Sync>>
             where $($name: Kind),+
         {
-            type ConstructItem = ForkHandle;
+            type ConstructItem = (CallId, Reply);
             type ConstructError = Void;
             type ConstructFuture = Future<ConstructResult<Self>>;
-            type DeconstructItem = Vec<ForkHandle>;
-            type DeconstructError = Void;
+            type DeconstructItem = Request<Vec<ForkHandle>>;
+            type DeconstructError = WrappedError<<U as Kind>::DeconstructError>;
             type DeconstructFuture = Future<DeconstructResult<Self>>;
 
             fn deconstruct<C: Channel<Self::DeconstructItem, Self::ConstructItem>>(
                 self,
-                mut channel: C,
+                channel: C,
             ) -> Self::DeconstructFuture {
-                Box::pin(async move {
-                    let handles = channel.next().await.unwrap();
-                    channel
-                        .send(
-                            channel
-                                .fork((self)($(channel.get_fork::<$name>(handles[$n as usize]).await.unwrap()),+))
-                                .await
-                                .unwrap(),
-                        )
-                        .unwrap_or_else(|_| panic!())
-                        .await;
-                    Ok(())
-                })
+                Box::pin(serve(channel, move |channel, handles: Vec<ForkHandle>| {
+                    let this = self.clone();
+                    $(let $nn = argument::<$name, _>(channel, &handles, $n);)+
+                    Box::pin(async move { Ok((this)($($nn.await?),+)) })
+                }))
             }
             fn construct<C: Channel<Self::ConstructItem, Self::DeconstructItem>>(
                 channel: C,
             ) -> Self::ConstructFuture {
                 Box::pin(async move {
-                    let channel = Arc::new(Mutex::new(channel));
+                    let caller = Caller::new(channel);
                     let closure: Arc<Box<dyn Fn($($name),+) -> U + Send + Sync>> =
                         Arc::new(Box::new(move |$($name),+| {
-                            let channel = channel.clone();
-                            U::flatten(async move {
-                                let mut channel = channel.lock().await;
-                                let handles = vec![
-                                    $(channel.fork::<$name>($name).await.unwrap()),+
-                                ];
-                                channel.send(handles).unwrap_or_else(|_| panic!()).await;
-                                let handle = channel.next().await.expect("test2");
-                                channel.get_fork(handle).await
+                            caller.call(move |channel: &C| {
+                                $(let $nn = channel.fork::<$name>($name);)+
+                                Box::pin(async move { Ok(vec![$($nn.await?),+]) })
                             })
                         }));
                     Ok(closure)
                 })
             }
-        })+
+        }
+        track_impl!($($name)+);
+        )+
     }
 }
 
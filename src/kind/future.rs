@@ -5,14 +5,17 @@ use crate::{
     ConstructResult, DeconstructResult, Kind,
 };
 
-use futures::{SinkExt, StreamExt};
+use futures::{
+    future::{select, Either},
+    SinkExt, StreamExt,
+};
 
-use super::WrappedError;
+use super::{Cancel, Lost, TransportError, WrappedError};
 
 #[kind]
 impl<T> Kind for Future<T>
 where
-    T: Kind,
+    T: Lost,
 {
     type ConstructItem = ForkHandle;
     type ConstructError = T::ConstructError;
@@ -25,19 +28,34 @@ where
         mut channel: C,
     ) -> Self::DeconstructFuture {
         Box::pin(async move {
+            // Anything received from the construct side means the future was dropped there.
+            let item = match select(self, channel.next()).await {
+                Either::Left((item, _)) => item,
+                Either::Right(_) => return Ok(()),
+            };
             Ok(channel
-                .send(channel.fork(self.await).await?)
+                .send(channel.fork(item).await?)
                 .await
                 .map_err(WrappedError::Send)?)
         })
     }
     fn construct<C: Channel<Self::ConstructItem, Self::DeconstructItem>>(
-        mut channel: C,
+        channel: C,
     ) -> Self::ConstructFuture {
         Box::pin(async move {
             Ok(Box::pin(async move {
-                let handle = channel.next().await.unwrap();
-                channel.get_fork::<T>(handle).await.unwrap()
+                let mut channel = Cancel::new(channel);
+                match channel.next().await {
+                    Some(handle) => {
+                        let channel = channel.disarm();
+                        match channel.get_fork::<T>(handle).await {
+                            Ok(item) => item,
+                            Err(e) => <T as Lost>::lost(TransportError::new(e.into())),
+                        }
+                    }
+                    // The channel closes without an item only if the connection was lost.
+                    None => <T as Lost>::lost(channel.disarm().lost()),
+                }
             }) as Future<T>)
         })
     }
@@ -1,5 +1,6 @@
 mod array;
 mod collections;
+pub mod deadline;
 mod default;
 mod error;
 mod functions;
@@ -7,6 +8,7 @@ mod future;
 mod iterator;
 mod option;
 mod phantom_data;
+pub mod pipeline;
 mod primitives;
 mod result;
 mod serde;
@@ -23,17 +25,30 @@ pub use default::Default;
 pub use iterator::Iterator;
 pub use sink_stream::SinkStream;
 
+use alloc::sync::Arc;
 use anyhow::Error;
-use core::pin::Pin;
+use core::{
+    any::type_name,
+    fmt::{self, Display, Formatter},
+    ops::{Deref, DerefMut},
+    pin::Pin,
+};
 use futures::{
-    stream::once, Future as IFuture, FutureExt, Sink as ISink, Stream as IStream, StreamExt,
+    channel::oneshot, future::ready, stream::once, Future as IFuture, FutureExt, Sink as ISink,
+    SinkExt, Stream as IStream, StreamExt,
 };
 use std::error::Error as StdError;
 use thiserror::Error;
 
-use crate::{channel::ChannelError, Kind};
+use crate::{
+    channel::{ChannelError, Fork},
+    core::spawn,
+    Kind,
+};
+use deadline::Deadline;
+use pipeline::{Dispatch, Pipe, PipelineError};
 
-#[derive(Error, Kind, Debug)]
+#[derive(Error, Debug)]
 #[error("transport error: {cause}")]
 pub struct TransportError {
     #[source]
@@ -44,6 +59,59 @@ impl TransportError {
     fn new(cause: Error) -> Self {
         TransportError { cause }
     }
+
+    pub(crate) fn lost() -> Self {
+        TransportError::new(anyhow::anyhow!("connection lost"))
+    }
+
+    /// The loss of a connection that was ended by `cause`, which remains available through
+    /// `source` so that it can be downcast to the error that ended the connection.
+    pub(crate) fn failed(cause: Arc<Error>) -> Self {
+        TransportError::new(Failed(cause).into())
+    }
+}
+
+/// A `Kind` that can represent the loss of the connection it was being received over, with which
+/// a remote `Future` of it resolves should that happen. Only `Future`s of such `Kind`s can be
+/// carried, as they would otherwise have nothing to resolve with.
+///
+/// This is implemented for `TransportError`, for `anyhow::Error` and for `Result`s with an error
+/// type that can be converted from a `TransportError`, as that of every remote call must be.
+pub trait Lost: Kind {
+    fn lost(error: TransportError) -> Self;
+}
+
+impl Lost for TransportError {
+    fn lost(error: TransportError) -> Self {
+        error
+    }
+}
+
+impl Lost for Error {
+    fn lost(error: TransportError) -> Self {
+        error.into()
+    }
+}
+
+impl<T: Kind, E: Kind + From<TransportError>> Lost for Result<T, E> {
+    fn lost(error: TransportError) -> Self {
+        Err(E::from(error))
+    }
+}
+
+#[derive(Debug)]
+struct Failed(Arc<Error>);
+
+impl Display for Failed {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        write!(formatter, "connection lost: {}", self.0)
+    }
+}
+
+impl StdError for Failed {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&**self.0)
+    }
 }
 
 pub type Future<T> = Pin<Box<dyn IFuture<Output = T> + Sync + Send>>;
@@ -64,9 +132,64 @@ pub trait Flatten: Sized {
     >(
         fut: F,
     ) -> Self;
+    /// As `flatten`, where `fut` is the result of a remote call on which methods may be called
+    /// through `pipe` before it resolves. Only `#[object]` trait objects make use of `pipe`.
+    fn flatten_pipelined<
+        E: 'static + Sync + Send + Into<Error>,
+        F: IFuture<Output = Result<Self, E>> + Sync + Send + 'static,
+        P: Pipe,
+    >(
+        fut: F,
+        _: P,
+    ) -> Self {
+        Self::flatten(fut)
+    }
+    #[doc(hidden)]
+    fn retain<C: Fork>(self) -> (Self, Option<Box<dyn Dispatch<C>>>) {
+        (self, None)
+    }
+    /// Bounds this result of a remote call by `deadline`, for types that resolve later.
+    #[doc(hidden)]
+    fn bound(self, _: Deadline) -> Self {
+        self
+    }
+}
+
+/// Separates the failure of `item` from its eventual value, so that the value alone can be
+/// pipelined on. Calls pipelined on a failed call fail in turn, but as the failure itself can
+/// only be described by its type it is handed back through the receiver.
+fn split<T: Kind, U: Sync + Send + 'static>(
+    item: Fallible<T, U>,
+) -> (Fallible<T, PipelineError>, oneshot::Receiver<U>) {
+    let (sender, receiver) = oneshot::channel();
+    let object = item.map(move |item| {
+        item.map_err(|e| {
+            let _ = sender.send(e);
+            PipelineError::new(anyhow::anyhow!(
+                "pipelined on a call that failed with {}",
+                type_name::<U>()
+            ))
+        })
+    });
+    (Box::pin(object), receiver)
 }
 
-impl<U: From<TransportError> + Sync + Send, T> Flatten for Fallible<T, U> {
+/// Reverses `split`, resolving to the failure of the original item if it failed.
+fn join<T: Kind, U: From<TransportError> + Sync + Send + 'static>(
+    object: Fallible<T, PipelineError>,
+    failure: oneshot::Receiver<U>,
+) -> Fallible<T, U> {
+    Box::pin(async move {
+        match object.await {
+            Ok(item) => Ok(item),
+            Err(e) => Err(failure
+                .await
+                .unwrap_or_else(|_| U::from(TransportError::new(e.into())))),
+        }
+    })
+}
+
+impl<U: From<TransportError> + Sync + Send + 'static, T: Kind> Flatten for Fallible<T, U> {
     fn flatten<
         E: 'static + Sync + Send + Into<Error>,
         F: IFuture<Output = Result<Self, E>> + Sync + Send + 'static,
@@ -79,6 +202,34 @@ impl<U: From<TransportError> + Sync + Send, T> Flatten for Fallible<T, U> {
                 .await
         })
     }
+    /// Where the eventual value is an `#[object]` trait object this resolves at once to a proxy
+    /// of it, so that methods may be called on it before the call has returned. The failure of
+    /// the call is then seen only as the failure of those methods.
+    fn flatten_pipelined<
+        E: 'static + Sync + Send + Into<Error>,
+        F: IFuture<Output = Result<Self, E>> + Sync + Send + 'static,
+        P: Pipe,
+    >(
+        fut: F,
+        pipe: P,
+    ) -> Self {
+        let (object, failure) = split(<Self as Flatten>::flatten(fut));
+        match T::pipeline(object, pipe) {
+            Ok(proxy) => Box::pin(ready(Ok(proxy))),
+            Err(object) => join(object, failure),
+        }
+    }
+    #[doc(hidden)]
+    fn retain<C: Fork>(self) -> (Self, Option<Box<dyn Dispatch<C>>>) {
+        let (object, failure) = split(self);
+        let (object, target) = T::retain_pending(object);
+        (join(object, failure), target)
+    }
+    fn bound(self, deadline: Deadline) -> Self {
+        deadline::bound(Some(deadline), self, || {
+            U::from(TransportError::new(anyhow::anyhow!("deadline exceeded")))
+        })
+    }
 }
 
 impl<U: From<TransportError>, T> Flatten for Stream<Result<T, U>> {
@@ -104,6 +255,44 @@ impl<U: From<TransportError>, T> Flatten for Stream<Result<T, U>> {
     }
 }
 
+/// Notifies the deconstructing side of a `Kind` that it was dropped before construction
+/// completed, by sending `()` on the channel it wraps unless disarmed first.
+pub(crate) struct Cancel<C: ISink<()> + Sync + Send + Unpin + 'static>(Option<C>);
+
+impl<C: ISink<()> + Sync + Send + Unpin + 'static> Cancel<C> {
+    pub(crate) fn new(channel: C) -> Self {
+        Cancel(Some(channel))
+    }
+
+    pub(crate) fn disarm(mut self) -> C {
+        self.0.take().unwrap()
+    }
+}
+
+impl<C: ISink<()> + Sync + Send + Unpin + 'static> Deref for Cancel<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.0.as_ref().unwrap()
+    }
+}
+
+impl<C: ISink<()> + Sync + Send + Unpin + 'static> DerefMut for Cancel<C> {
+    fn deref_mut(&mut self) -> &mut C {
+        self.0.as_mut().unwrap()
+    }
+}
+
+impl<C: ISink<()> + Sync + Send + Unpin + 'static> Drop for Cancel<C> {
+    fn drop(&mut self) {
+        if let Some(mut channel) = self.0.take() {
+            spawn(async move {
+                let _ = channel.send(()).await;
+            });
+        }
+    }
+}
+
 pub trait AsKindMarker {}
 
 #[derive(Error, Debug)]
@@ -0,0 +1,92 @@
+//! Promise pipelining for `#[object]` trait objects.
+//!
+//! A method of a remote object that returns another object, either `Box<dyn Service>` or a
+//! `Fallible<Box<dyn Service>, E>`, returns immediately with a proxy for that object. Methods
+//! called on the proxy before the remote call has returned are sent to the peer at once and
+//! queued there against the pending result, so a chain of such calls costs a single round trip.
+//! Errors in any call of the chain surface in the results of the calls made on its result, so a
+//! `Fallible` returned this way resolves successfully even if the call it was returned by fails.
+
+use crate::{
+    channel::{Fork, ForkHandle},
+    kind::{deadline::Deadline, Fallible, Flatten},
+    reflect::MethodIndex,
+    Kind,
+};
+
+use anyhow::Error;
+use futures::TryFutureExt;
+use thiserror::Error;
+
+/// A failure of a pipelined call or of the call whose result it was made on.
+#[derive(Error, Debug, Clone)]
+#[error("{0}")]
+pub struct PipelineError(String);
+
+impl PipelineError {
+    pub fn new<E: Into<Error>>(error: E) -> Self {
+        PipelineError(format!("{}", error.into()))
+    }
+
+    /// The failure of a call on an object after a method taking it by value was called on it.
+    pub fn moved() -> Self {
+        PipelineError("object was moved by an earlier call".to_owned())
+    }
+}
+
+/// The caller side of pipelining, sending calls to methods of the eventual result of a remote
+/// call ahead of that result.
+pub trait Pipe: Clone + Sync + Send + 'static {
+    /// Calls the method with index `method` on the eventual result. If the result has already
+    /// been returned the arguments are handed back and the call should be made on it directly.
+    fn call<K: Kind, R: Kind + Flatten>(&self, method: MethodIndex, args: K) -> Result<R, K>;
+}
+
+/// A `Pipe` that never pipelines, used where a result is not that of a remote call.
+#[derive(Clone, Copy)]
+pub struct Direct;
+
+impl Pipe for Direct {
+    fn call<K: Kind, R: Kind + Flatten>(&self, _: MethodIndex, args: K) -> Result<R, K> {
+        Err(args)
+    }
+}
+
+/// The callee side of pipelining, an object retained after being returned so that pipelined
+/// calls may be made on it.
+pub trait Dispatch<C>: Sync + Send {
+    fn dispatch(
+        &self,
+        method: MethodIndex,
+        args: ForkHandle,
+        channel: &C,
+    ) -> Fallible<Retained<C>, PipelineError>;
+}
+
+type ForkFn<C> = Box<dyn FnOnce(&C) -> Fallible<ForkHandle, PipelineError> + Sync + Send>;
+
+/// The result of a call, to be forked back to the caller and possibly retained for calls
+/// pipelined on it.
+pub struct Retained<C> {
+    fork: ForkFn<C>,
+    target: Option<Box<dyn Dispatch<C>>>,
+}
+
+impl<C: Fork> Retained<C> {
+    /// Wraps the result of a call, bounding it by the deadline the call was made with, if any.
+    pub fn new<K: Kind + Flatten>(item: K) -> Self {
+        let item = match Deadline::current() {
+            Some(deadline) => item.bound(deadline),
+            None => item,
+        };
+        let (item, target) = item.retain::<C>();
+        Retained {
+            fork: Box::new(move |channel| Box::pin(channel.fork(item).map_err(PipelineError::new))),
+            target,
+        }
+    }
+
+    pub(crate) fn split(self) -> (ForkFn<C>, Option<Box<dyn Dispatch<C>>>) {
+        (self.fork, self.target)
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{
     channel::{Channel, ForkHandle},
     kind,
-    kind::Future,
+    kind::{Future, TransportError},
     ConstructResult, DeconstructResult, Kind,
 };
 
@@ -60,4 +60,7 @@ where
             )
         })
     }
+    fn lost(error: TransportError) -> Option<Self> {
+        E::lost(error).map(Err)
+    }
 }
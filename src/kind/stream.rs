@@ -5,9 +5,13 @@ use crate::{
     ConstructResult, DeconstructResult, Kind,
 };
 
-use futures::{stream::unfold, SinkExt, StreamExt};
+use futures::{
+    future::{select, Either},
+    stream::unfold,
+    SinkExt, StreamExt,
+};
 
-use super::WrappedError;
+use super::{Cancel, TransportError, WrappedError};
 
 #[kind]
 impl<T> Kind for Stream<T>
@@ -25,7 +29,13 @@ where
         mut channel: C,
     ) -> Self::DeconstructFuture {
         Box::pin(async move {
-            while let Some(item) = self.next().await {
+            loop {
+                // Anything received from the construct side means the stream was dropped there.
+                let item = match select(self.next(), channel.next()).await {
+                    Either::Left((Some(item), _)) => item,
+                    Either::Left((None, _)) => break,
+                    Either::Right(_) => return Ok(()),
+                };
                 channel
                     .send(Some(channel.fork(item).await?))
                     .await
@@ -39,15 +49,27 @@ where
         channel: C,
     ) -> Self::ConstructFuture {
         Box::pin(async move {
-            Ok(Box::pin(unfold(channel, |mut channel| {
-                async move {
-                    if let Some(handle) = channel.next().await.unwrap() {
-                        Some((channel.get_fork(handle).await.unwrap(), channel))
-                    } else {
-                        None
+            Ok(
+                Box::pin(unfold(Some(Cancel::new(channel)), |channel| async move {
+                    let mut channel = channel?;
+                    match channel.next().await {
+                        Some(Some(handle)) => match channel.get_fork::<T>(handle).await {
+                            Ok(item) => Some((item, Some(channel))),
+                            Err(e) => {
+                                channel.disarm();
+                                T::lost(TransportError::new(e.into())).map(|item| (item, None))
+                            }
+                        },
+                        Some(None) => {
+                            channel.disarm();
+                            None
+                        }
+                        // The channel closes before the end of the stream only if the
+                        // connection was lost, which is yielded once if `T` can represent it.
+                        None => T::lost(channel.disarm().lost()).map(|item| (item, None)),
                     }
-                }
-            })) as Stream<T>)
+                })) as Stream<T>,
+            )
         })
     }
 }
@@ -8,13 +8,16 @@ extern crate alloc;
 pub mod channel;
 #[doc(inline)]
 pub use channel::OnTo;
-use channel::{Channel, Target};
+use channel::{Channel, Fork, Target};
 pub mod format;
 #[doc(inline)]
 pub use format::{ApplyDecode, ApplyEncode};
 pub mod core;
 pub mod kind;
-use kind::{ConstructResult, DeconstructResult};
+use kind::{
+    pipeline::{Dispatch, Pipe, PipelineError},
+    ConstructResult, DeconstructResult, Fallible, TransportError,
+};
 pub mod reflect;
 pub mod replicate;
 
@@ -129,6 +132,8 @@ pub use derive::export;
 
 pub use derive::kind;
 
+#[doc(hidden)]
+pub use anyhow;
 #[doc(hidden)]
 pub use futures;
 #[doc(hidden)]
@@ -187,6 +192,41 @@ pub trait Kind: Any + Sized + Sync + Send + Unpin + 'static {
         channel: C,
     ) -> Self::DeconstructFuture;
 
+    /// Represents the loss of the connection this `Kind` was being received over, or the failure
+    /// to construct it from what was received, if it can. A remote `Stream` of such a `Kind`
+    /// yields this value should either happen, and a remote `Stream` of any other `Kind` ends.
+    /// Remote `Future`s resolve with `kind::Lost::lost` instead, which their `Kind` must
+    /// implement.
+    ///
+    /// This is implemented for `TransportError`, for `Result`s with an error type that can
+    /// represent loss, and by the derive macro for enums with a variant marked `#[kind(lost)]`,
+    /// which must wrap only a `TransportError`.
+    #[doc(hidden)]
+    fn lost(_: TransportError) -> Option<Self> {
+        None
+    }
+
+    /// Returns a proxy of the eventual `object`, the successful result of a remote call, on
+    /// which methods may be called through `pipe` before it resolves. `object` is handed back
+    /// if this `Kind` cannot be pipelined on, as is the case for all but `#[object]` trait
+    /// objects.
+    #[doc(hidden)]
+    fn pipeline<P: Pipe>(
+        object: Fallible<Self, PipelineError>,
+        _: P,
+    ) -> Result<Self, Fallible<Self, PipelineError>> {
+        Err(object)
+    }
+
+    /// Retains the eventual `object`, the successful result of a call, so that calls pipelined
+    /// on it may be dispatched to it once it resolves.
+    #[doc(hidden)]
+    fn retain_pending<C: Fork>(
+        object: Fallible<Self, PipelineError>,
+    ) -> (Fallible<Self, PipelineError>, Option<Box<dyn Dispatch<C>>>) {
+        (object, None)
+    }
+
     #[doc(hidden)]
     const USE_KIND_MACRO_TO_GENERATE_THIS_FIELD: [u8; 32];
 }
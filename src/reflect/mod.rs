@@ -1,8 +1,10 @@
 use crate::Kind;
+use alloc::sync::Arc;
 use core::{
     any::{Any, TypeId},
     fmt::{self, Display, Formatter},
 };
+use std::sync::Mutex;
 use thiserror::Error;
 
 pub type MethodIndex = u8;
@@ -63,6 +65,30 @@ pub trait Reflected: 'static {
     type ErasedShim: From<Box<Self>>;
     #[doc(hidden)]
     const DO_NOT_IMPLEMENT_THIS_MARKER_TRAIT_MANUALLY: ();
+    /// Whether a method of the trait or of a supertrait takes its receiver by value.
+    #[doc(hidden)]
+    const MOVES: bool;
+    #[doc(hidden)]
+    fn share(object: Arc<Mutex<Box<Self>>>, guard: Arc<dyn Any + Sync + Send>) -> Box<Self>;
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl<T: 'static> Sealed for T {}
+}
+
+/// Recovers the concrete type behind an `#[object]` trait object, which requires it of every
+/// implementor. Implemented for all `'static` types and for nothing else.
+#[doc(hidden)]
+pub trait Concrete: sealed::Sealed + Any {
+    fn concrete(&self) -> &dyn Any;
+}
+
+impl<T: 'static> Concrete for T {
+    fn concrete(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -105,6 +131,10 @@ impl Reflected for SomeTrait {
     type Shim = ();
     type ErasedShim = ();
     const DO_NOT_IMPLEMENT_THIS_MARKER_TRAIT_MANUALLY: () = ();
+    const MOVES: bool = false;
+    fn share(object: Arc<Mutex<Box<Self>>>, _: Arc<dyn Any + Sync + Send>) -> Box<Self> {
+        match **object.lock().unwrap() {}
+    }
 }
 
 impl From<Box<SomeTrait>> for () {
@@ -3,7 +3,7 @@ use crate::{
     kind,
     kind::{ConstructResult, DeconstructResult, Future, WrappedError},
     reflect::{
-        CallError, Cast, CastError, Erased, MethodIndex, MethodTypes, NameError, OutOfRangeError,
+        CallError, CastError, Erased, MethodIndex, MethodTypes, NameError, OutOfRangeError,
         Reflected, Trait,
     },
     Kind,
@@ -11,7 +11,7 @@ use crate::{
 
 use alloc::sync::Arc;
 use core::any::{Any, TypeId};
-use futures::{SinkExt, StreamExt};
+use futures::{SinkExt, StreamExt, TryFutureExt};
 use std::sync::Mutex;
 
 pub use derive::Share;
@@ -26,17 +26,79 @@ impl<T: Clone> Share for T {
     }
 }
 
-pub struct Shared<T: Trait<T> + Reflected + ?Sized>(Arc<Mutex<Box<T>>>);
+type Callback = Arc<dyn Fn() + Sync + Send>;
+
+#[derive(Default)]
+struct Proxies {
+    count: Mutex<usize>,
+    on_release: Mutex<Vec<Callback>>,
+}
+
+impl Proxies {
+    fn count(&self) -> usize {
+        *self.count.lock().unwrap()
+    }
+    fn on_release(&self, callback: impl Fn() + Sync + Send + 'static) {
+        self.on_release.lock().unwrap().push(Arc::new(callback));
+    }
+}
+
+/// Held by a remote proxy of a `Shared` or `Tracked` for as long as that proxy is alive.
+struct Proxy(Arc<Proxies>);
+
+impl Proxy {
+    fn new(proxies: Arc<Proxies>) -> Self {
+        *proxies.count.lock().unwrap() += 1;
+        Proxy(proxies)
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        let mut count = self.0.count.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            drop(count);
+            let callbacks = self.0.on_release.lock().unwrap().clone();
+            callbacks.iter().for_each(|callback| callback());
+        }
+    }
+}
+
+/// A shared reflected trait object.
+///
+/// Each time a `Shared` is sent over a channel the peer receives a proxy of the same object
+/// rather than taking ownership of it. The object is dropped once the last `Shared` and the
+/// last remote proxy of it are both gone.
+pub struct Shared<T: Trait<T> + Reflected + ?Sized> {
+    item: Arc<Mutex<Box<T>>>,
+    proxies: Arc<Proxies>,
+}
 
 impl<T: Trait<T> + Reflected + ?Sized> Shared<T> {
     pub fn new(item: Box<T>) -> Self {
-        Shared(Arc::new(Mutex::new(item)))
+        Shared {
+            item: Arc::new(Mutex::new(item)),
+            proxies: Arc::new(Proxies::default()),
+        }
+    }
+    /// Returns the number of remote proxies of this object that are currently alive.
+    pub fn proxies(&self) -> usize {
+        self.proxies.count()
+    }
+    /// Registers a callback that is invoked each time the last remote proxy of this object
+    /// is dropped by its holder.
+    pub fn on_release(&self, callback: impl Fn() + Sync + Send + 'static) {
+        self.proxies.on_release(callback)
     }
 }
 
 impl<T: Trait<T> + Reflected + ?Sized> Share for Shared<T> {
     fn share(&self) -> Self {
-        Shared(self.0.share())
+        Shared {
+            item: self.item.share(),
+            proxies: self.proxies.share(),
+        }
     }
 }
 
@@ -46,56 +108,56 @@ impl<T: Trait<T> + Reflected + ?Sized> Trait<T> for Shared<T> {
         index: MethodIndex,
         args: Vec<Box<dyn Any + Send + Sync>>,
     ) -> Result<Box<dyn Any + Send + Sync>, CallError> {
-        self.0.lock().unwrap().call(index, args)
+        self.item.lock().unwrap().call(index, args)
     }
     fn call_mut(
         &mut self,
         index: MethodIndex,
         args: Vec<Box<dyn Any + Send + Sync>>,
     ) -> Result<Box<dyn Any + Send + Sync>, CallError> {
-        self.0.lock().unwrap().call_mut(index, args)
+        self.item.lock().unwrap().call_mut(index, args)
     }
     fn call_move(
         self: Box<Self>,
         index: MethodIndex,
         args: Vec<Box<dyn Any + Send + Sync>>,
     ) -> Result<Box<dyn Any + Send + Sync>, CallError> {
-        Arc::try_unwrap(self.0)
+        Arc::try_unwrap(self.item)
             .unwrap_or_else(|_| panic!())
             .into_inner()
             .unwrap()
             .call_move(index, args)
     }
     fn by_name(&self, name: &'_ str) -> Result<MethodIndex, NameError> {
-        self.0.lock().unwrap().by_name(name)
+        self.item.lock().unwrap().by_name(name)
     }
     fn count(&self) -> MethodIndex {
-        self.0.lock().unwrap().count()
+        self.item.lock().unwrap().count()
     }
     fn name_of(&self, index: MethodIndex) -> Result<String, OutOfRangeError> {
-        self.0.lock().unwrap().name_of(index)
+        self.item.lock().unwrap().name_of(index)
     }
     fn this(&self) -> TypeId {
-        self.0.lock().unwrap().this()
+        self.item.lock().unwrap().this()
     }
     fn name(&self) -> String {
-        self.0.lock().unwrap().name()
+        self.item.lock().unwrap().name()
     }
     fn types(&self, index: MethodIndex) -> Result<MethodTypes, OutOfRangeError> {
-        self.0.lock().unwrap().types(index)
+        self.item.lock().unwrap().types(index)
     }
     fn supertraits(&self) -> Vec<TypeId> {
-        self.0.lock().unwrap().supertraits()
+        self.item.lock().unwrap().supertraits()
     }
     fn upcast_erased(self: Box<Self>, ty: TypeId) -> Result<Box<dyn Erased>, CastError> {
-        Arc::try_unwrap(self.0)
+        Arc::try_unwrap(self.item)
             .unwrap_or_else(|_| panic!())
             .into_inner()
             .unwrap()
             .upcast_erased(ty)
     }
     fn erase(self: Box<Self>) -> Box<dyn Erased> {
-        Arc::try_unwrap(self.0)
+        Arc::try_unwrap(self.item)
             .unwrap_or_else(|_| panic!())
             .into_inner()
             .unwrap()
@@ -119,12 +181,9 @@ where
         mut channel: C,
     ) -> Self::DeconstructFuture {
         Box::pin(async move {
+            let proxy = Arc::new(Proxy::new(self.proxies));
             Ok(channel
-                .send(
-                    channel
-                        .fork::<Box<T>>(Box::new(self).erase().downcast().unwrap())
-                        .await?,
-                )
+                .send(channel.fork::<Box<T>>(T::share(self.item, proxy)).await?)
                 .await
                 .map_err(WrappedError::Send)?)
         })
@@ -141,3 +200,86 @@ where
         })
     }
 }
+
+/// A kind whose remote proxy can keep a guard alive, dropping it once the peer has dropped the
+/// proxy. Implemented for reflected trait objects and boxed closures.
+pub trait Track: Kind {
+    #[doc(hidden)]
+    fn track(self, guard: Arc<dyn Any + Sync + Send>) -> Self;
+}
+
+impl<T: Trait<T> + Reflected + ?Sized> Track for Box<T>
+where
+    Box<T>: Kind,
+{
+    fn track(self, guard: Arc<dyn Any + Sync + Send>) -> Self {
+        T::share(Arc::new(Mutex::new(self)), guard)
+    }
+}
+
+/// A reflected trait object or boxed closure whose remote proxies are tracked.
+///
+/// A `Tracked` is sent exactly as the item it wraps, so the peer may construct it as either,
+/// but the owner is told once the peer has dropped its proxy. Unlike a `Shared`, the item is
+/// given up to the peer rather than kept, so its proxies are observed through a `Tracker`
+/// obtained before it is sent. A `Tracked` constructed by the peer tracks the proxies of the
+/// item that it in turn sends on, independently of those of the one it was sent as.
+pub struct Tracked<T: Track> {
+    item: T,
+    proxies: Arc<Proxies>,
+}
+
+impl<T: Track> Tracked<T> {
+    pub fn new(item: T) -> Self {
+        Tracked {
+            item,
+            proxies: Arc::new(Proxies::default()),
+        }
+    }
+    /// Returns a handle through which the remote proxies of this item can still be observed
+    /// once it has been sent.
+    pub fn tracker(&self) -> Tracker {
+        Tracker(self.proxies.clone())
+    }
+    pub fn into_inner(self) -> T {
+        self.item
+    }
+}
+
+/// A handle to the remote proxies of the item of a `Tracked`.
+#[derive(Clone)]
+pub struct Tracker(Arc<Proxies>);
+
+impl Tracker {
+    /// Returns the number of remote proxies of the tracked item that are currently alive.
+    pub fn proxies(&self) -> usize {
+        self.0.count()
+    }
+    /// Registers a callback that is invoked once the remote proxy of the tracked item is
+    /// dropped by its holder.
+    pub fn on_release(&self, callback: impl Fn() + Sync + Send + 'static) {
+        self.0.on_release(callback)
+    }
+}
+
+#[kind]
+impl<T: Track> Kind for Tracked<T> {
+    type ConstructItem = T::ConstructItem;
+    type ConstructError = T::ConstructError;
+    type ConstructFuture = Future<ConstructResult<Self>>;
+    type DeconstructItem = T::DeconstructItem;
+    type DeconstructError = T::DeconstructError;
+    type DeconstructFuture = T::DeconstructFuture;
+    fn deconstruct<C: Channel<Self::DeconstructItem, Self::ConstructItem>>(
+        self,
+        channel: C,
+    ) -> Self::DeconstructFuture {
+        let proxy = Arc::new(Proxy::new(self.proxies));
+        self.item.track(proxy).deconstruct(channel)
+    }
+    fn construct<C: Channel<Self::ConstructItem, Self::DeconstructItem>>(
+        channel: C,
+    ) -> Self::ConstructFuture {
+        Box::pin(T::construct(channel).map_ok(Tracked::new))
+    }
+}